@@ -3,6 +3,7 @@
 #include <iostream>
 
 #include "ctranslate2/translator.h"
+#include "ctranslate2/models/model_reader.h"
 
 #include "rust/cxx.h"
 
@@ -60,6 +61,122 @@ class MyDataClass {
   private: std::vector < std::vector < std::string >> m_data;
 };
 
+// gives rust access to the full translation results (hypotheses, scores and attention)
+class MyResultClass {
+  public: MyResultClass(std::vector < ctranslate2::TranslationResult > data = {}): m_data(data) {}
+
+  // gets amount of examples
+  size_t getLength() const {
+    return m_data.size();
+  }
+
+  // gets amount of hypotheses of an example
+  size_t getNumHypotheses(const size_t index) const {
+    return get(index).num_hypotheses();
+  }
+
+  // gets the tokens of a hypothesis
+  rust::Vec < rust::String > getHypothesis(const size_t index, const size_t hypothesis) const {
+    const auto & hypotheses = get(index).hypotheses;
+    if (hypothesis >= hypotheses.size()) {
+      throw std::out_of_range("Hypothesis out of range");
+    }
+    rust::Vec < rust::String > sentence;
+    for (const auto & str: hypotheses[hypothesis]) {
+      sentence.push_back(str);
+    }
+    return sentence;
+  }
+
+  // gets the scores of all hypotheses (empty if scores were not requested)
+  rust::Vec < float > getScores(const size_t index) const {
+    rust::Vec < float > scores;
+    for (const auto score: get(index).scores) {
+      scores.push_back(score);
+    }
+    return scores;
+  }
+
+  // gets the amount of attention rows of a hypothesis (0 if attention was not requested)
+  size_t getAttentionLength(const size_t index, const size_t hypothesis) const {
+    const auto & attention = get(index).attention;
+    return hypothesis < attention.size() ? attention[hypothesis].size() : 0;
+  }
+
+  // gets one attention row (attention of one target token over the source tokens)
+  rust::Vec < float > getAttentionRow(const size_t index, const size_t hypothesis, const size_t row) const {
+    if (row >= getAttentionLength(index, hypothesis)) {
+      throw std::out_of_range("Attention row out of range");
+    }
+    rust::Vec < float > values;
+    for (const auto value: get(index).attention[hypothesis][row]) {
+      values.push_back(value);
+    }
+    return values;
+  }
+
+  private: std::vector < ctranslate2::TranslationResult > m_data;
+
+  const ctranslate2::TranslationResult & get(const size_t index) const {
+    if (index >= m_data.size()) {
+      throw std::out_of_range("Index out of range");
+    }
+    return m_data[index];
+  }
+};
+
+// gives rust access to the scores of target sentences (token log-probs)
+class MyScoringClass {
+  public: MyScoringClass(std::vector < ctranslate2::ScoringResult > data = {}): m_data(data) {}
+
+  // gets amount of examples
+  size_t getLength() const {
+    return m_data.size();
+  }
+
+  // gets the scored target tokens (including the end token)
+  rust::Vec < rust::String > getTokens(const size_t index) const {
+    rust::Vec < rust::String > tokens;
+    for (const auto & str: get(index).tokens) {
+      tokens.push_back(str);
+    }
+    return tokens;
+  }
+
+  // gets the log-prob of each scored token
+  rust::Vec < float > getTokenScores(const size_t index) const {
+    rust::Vec < float > scores;
+    for (const auto score: get(index).tokens_score) {
+      scores.push_back(score);
+    }
+    return scores;
+  }
+
+  private: std::vector < ctranslate2::ScoringResult > m_data;
+
+  const ctranslate2::ScoringResult & get(const size_t index) const {
+    if (index >= m_data.size()) {
+      throw std::out_of_range("Index out of range");
+    }
+    return m_data[index];
+  }
+};
+
+// model files passed from rust, read by ctranslate2 instead of a model directory
+class MyModelReader {
+  public: MyModelReader(const std::string & model_id): m_reader(model_id) {}
+
+  void registerFile(rust::Str filename, rust::Slice < const uint8_t > content) {
+    m_reader.register_file(std::string(filename), std::string(content.begin(), content.end()));
+  }
+
+  ctranslate2::models::ModelMemoryReader & get() {
+    return m_reader;
+  }
+
+  private: ctranslate2::models::ModelMemoryReader m_reader;
+};
+
 class MyTranslator {
   public: MyTranslator(const std::string & model_path,
     const bool use_gpu, const bool fast): m_translator(std::string(model_path),
@@ -68,6 +185,13 @@ class MyTranslator {
       0
     }, {}) {}
 
+  MyTranslator(ctranslate2::models::ModelReader & model_reader,
+    const bool use_gpu, const bool fast): m_translator(model_reader,
+    use_gpu ? ctranslate2::Device::CUDA : ctranslate2::Device::CPU,
+    fast ? (use_gpu ? ctranslate2::ComputeType::FLOAT16 : ctranslate2::ComputeType::INT8) : ctranslate2::ComputeType::DEFAULT, {
+      0
+    }, {}) {}
+
   std::unique_ptr < MyDataClass > translate_batch(const MyDataClass & data,
     const CTranslateOptions & options,
     const size_t max_batch_size = 0,
@@ -79,6 +203,17 @@ class MyTranslator {
     return std::make_unique < MyDataClass > (extract(translation));
   }
 
+  std::unique_ptr < MyResultClass > translate_batch_results(const MyDataClass & data,
+    const CTranslateOptions & options,
+    const size_t max_batch_size = 0,
+    const bool batch_type_example = true) {
+    const std::vector < std::vector < std::string >> batch(data.get_all());
+    auto translation = m_translator.translate_batch(batch, options.get(), max_batch_size,
+      batch_type_example ? ctranslate2::BatchType::Examples :
+      ctranslate2::BatchType::Tokens);
+    return std::make_unique < MyResultClass > (translation);
+  }
+
   std::unique_ptr < MyDataClass > translate_batch_target(const MyDataClass & data, rust::Vec < rust::String > target,
     const CTranslateOptions & options,
     const size_t max_batch_size = 0,
@@ -96,6 +231,17 @@ class MyTranslator {
     return std::make_unique < MyDataClass > (extract(translation));
   }
 
+  std::unique_ptr < MyScoringClass > score_batch(const MyDataClass & source,
+    const MyDataClass & target,
+    const size_t max_batch_size = 0,
+    const bool batch_type_example = true) {
+    auto scores = m_translator.score_batch(source.get_all(), target.get_all(),
+      ctranslate2::ScoringOptions(), max_batch_size,
+      batch_type_example ? ctranslate2::BatchType::Examples :
+      ctranslate2::BatchType::Tokens);
+    return std::make_unique < MyScoringClass > (scores);
+  }
+
   private: ctranslate2::Translator m_translator;
 
   std::vector < std::vector < std::string >> extract(const std::vector < ctranslate2::TranslationResult > translation) const {
@@ -116,6 +262,15 @@ std::unique_ptr < MyTranslator > new_translator(const std::string & model,
   return std::make_unique < MyTranslator > (model, gpu, fast);
 }
 
+std::unique_ptr < MyModelReader > new_model_reader(const std::string & model_id) {
+  return std::make_unique < MyModelReader > (model_id);
+}
+
+std::unique_ptr < MyTranslator > new_translator_from_reader(MyModelReader & reader,
+  const bool gpu, const bool fast) {
+  return std::make_unique < MyTranslator > (reader.get(), gpu, fast);
+}
+
 std::unique_ptr < MyDataClass > new_data() {
   return std::make_unique < MyDataClass > ();
 }
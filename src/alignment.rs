@@ -0,0 +1,211 @@
+//! Hard word alignments extracted from the attention returned with `return_attention`.
+
+use crate::TranslationResult;
+
+/// SentencePiece marker for the start of a word.
+pub const WORD_MARKER: char = '▁';
+
+/// How attention weights are turned into alignment links.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlignmentMethod {
+    /// Align each target token to the source token with the highest attention.
+    #[default]
+    Argmax,
+    /// Align each target token to every source token with an attention weight >= the threshold.
+    Threshold(f32),
+}
+
+/// Word level alignment between a source sentence and one hypothesis.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WordAlignment {
+    pub source_words: Vec<String>,
+    pub target_words: Vec<String>,
+    /// Sorted `(source word, target word)` links.
+    pub links: Vec<(usize, usize)>,
+}
+
+impl WordAlignment {
+    /// Source words aligned to the given target word.
+    pub fn source_for(&self, target: usize) -> Vec<usize> {
+        self.links
+            .iter()
+            .filter(|(_, t)| *t == target)
+            .map(|(s, _)| *s)
+            .collect()
+    }
+
+    /// Target words aligned to the given source word.
+    pub fn target_for(&self, source: usize) -> Vec<usize> {
+        self.links
+            .iter()
+            .filter(|(s, _)| *s == source)
+            .map(|(_, t)| *t)
+            .collect()
+    }
+
+    /// Links in Pharaoh format, e.g. `0-0 1-2`.
+    pub fn to_pharaoh(&self) -> String {
+        self.links
+            .iter()
+            .map(|(s, t)| format!("{}-{}", s, t))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Token level `(source token, target token)` links from an attention matrix indexed as
+/// `[target token][source token]`.
+///
+/// Rows and columns past `target_length` / `source_length` belong to special tokens
+/// (e.g. `</s>`) and are ignored.
+pub fn align_tokens(
+    attention: &[Vec<f32>],
+    source_length: usize,
+    target_length: usize,
+    method: AlignmentMethod,
+) -> Vec<(usize, usize)> {
+    let mut links = vec![];
+    for (target, row) in attention.iter().take(target_length).enumerate() {
+        let row = &row[..row.len().min(source_length)];
+        match method {
+            AlignmentMethod::Argmax => {
+                let best =
+                    row.iter()
+                        .enumerate()
+                        .fold(None, |best: Option<(usize, f32)>, (i, &w)| match best {
+                            Some((_, b)) if b >= w => best,
+                            _ => Some((i, w)),
+                        });
+                if let Some((source, _)) = best {
+                    links.push((source, target));
+                }
+            }
+            AlignmentMethod::Threshold(threshold) => {
+                links.extend(
+                    row.iter()
+                        .enumerate()
+                        .filter(|(_, &w)| w >= threshold)
+                        .map(|(source, _)| (source, target)),
+                );
+            }
+        }
+    }
+    links.sort_unstable();
+    links
+}
+
+/// Word index of every token, using the `▁` convention: a token starting with `▁` opens a new word,
+/// any other token continues the previous one.
+pub fn word_indices(tokens: &[String]) -> Vec<usize> {
+    let mut word = 0;
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            if i > 0 && token.starts_with(WORD_MARKER) {
+                word += 1;
+            }
+            word
+        })
+        .collect()
+}
+
+/// Merges subword tokens into words, removing the `▁` markers.
+pub fn merge_words(tokens: &[String]) -> Vec<String> {
+    let mut words: Vec<String> = vec![];
+    for (token, word) in tokens.iter().zip(word_indices(tokens)) {
+        if words.len() <= word {
+            words.push(String::new());
+        }
+        words[word].push_str(&token.replace(WORD_MARKER, ""));
+    }
+    words
+}
+
+/// Word alignment of one hypothesis given its attention matrix.
+pub fn align_words(
+    source: &[String],
+    target: &[String],
+    attention: &[Vec<f32>],
+    method: AlignmentMethod,
+) -> WordAlignment {
+    let source_words = word_indices(source);
+    let target_words = word_indices(target);
+    let mut links: Vec<(usize, usize)> =
+        align_tokens(attention, source.len(), target.len(), method)
+            .into_iter()
+            .map(|(s, t)| (source_words[s], target_words[t]))
+            .collect();
+    links.sort_unstable();
+    links.dedup();
+    WordAlignment {
+        source_words: merge_words(source),
+        target_words: merge_words(target),
+        links,
+    }
+}
+
+/// Word alignments of every hypothesis of a result translated with `return_attention`.
+///
+/// Returns an error if the result does not contain attention.
+pub fn align_result(
+    source: &[String],
+    result: &TranslationResult,
+    method: AlignmentMethod,
+) -> Result<Vec<WordAlignment>, String> {
+    if result.attention.len() != result.hypotheses.len() {
+        return Err("translation result has no attention, set return_attention".to_string());
+    }
+    Ok(result
+        .hypotheses
+        .iter()
+        .zip(&result.attention)
+        .map(|(target, attention)| align_words(source, target, attention, method))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(v: &[&str]) -> Vec<String> {
+        v.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn merges_subwords() {
+        let source = tokens(&["▁H", "ell", "o", "▁world", "!"]);
+        assert_eq!(word_indices(&source), vec![0, 0, 0, 1, 1]);
+        assert_eq!(merge_words(&source), vec!["Hello", "world!"]);
+    }
+
+    #[test]
+    fn aligns_words() {
+        let source = tokens(&["▁H", "ell", "o", "▁world"]);
+        let target = tokens(&["▁world", "▁hel", "lo"]);
+        // Last column is the source </s> token.
+        let attention = vec![
+            vec![0.1, 0.0, 0.1, 0.7, 0.1],
+            vec![0.6, 0.2, 0.1, 0.0, 0.1],
+            vec![0.1, 0.3, 0.4, 0.1, 0.1],
+            vec![0.0, 0.0, 0.0, 0.0, 1.0],
+        ];
+        let alignment = align_words(&source, &target, &attention, AlignmentMethod::Argmax);
+        assert_eq!(alignment.links, vec![(0, 1), (1, 0)]);
+        assert_eq!(alignment.to_pharaoh(), "0-1 1-0");
+        let result = TranslationResult {
+            hypotheses: vec![target],
+            scores: vec![],
+            attention: vec![attention],
+        };
+        let thresholded = align_result(&source, &result, AlignmentMethod::Threshold(0.3)).unwrap();
+        assert_eq!(thresholded[0].links, vec![(0, 1), (1, 0)]);
+        assert!(align_result(
+            &source,
+            &TranslationResult::default(),
+            AlignmentMethod::Argmax
+        )
+        .unwrap()
+        .is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+//! Back-translation with a forward and a backward model, for round-trip consistency checks and
+//! synthetic parallel data.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::metrics::{chrf, token_overlap};
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundTripOptions {
+    /// Lines passed to each translator call.
+    pub chunk_size: usize,
+    pub max_batch_size: Option<usize>,
+    pub batch_type: BatchType,
+    pub forward_options: Option<TranslationOptions>,
+    pub backward_options: Option<TranslationOptions>,
+}
+
+impl Default for RoundTripOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024,
+            max_batch_size: None,
+            batch_type: BatchType::default(),
+            forward_options: None,
+            backward_options: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoundTrip {
+    pub source: String,
+    pub translation: String,
+    /// The translation translated back.
+    pub round_trip: String,
+    /// chrF of the round trip against the source, in `[0, 1]`.
+    pub chrf: f32,
+    /// Word overlap F1 of the round trip and the source, in `[0, 1]`.
+    pub token_overlap: f32,
+}
+
+/// A forward (source to target) and a backward (target to source) translator, e.g. two
+/// [`crate::CTranslator`]s.
+///
+/// For back-translation of target monolingual text, pass the target to source model as
+/// `forward`: the `translation`s are then the synthetic sources.
+pub struct BackTranslator<F: Translate, B: Translate> {
+    pub forward: F,
+    pub backward: B,
+    pub options: RoundTripOptions,
+}
+
+impl<F: Translate, B: Translate> BackTranslator<F, B> {
+    pub fn new(forward: F, backward: B) -> Self {
+        Self {
+            forward,
+            backward,
+            options: RoundTripOptions::default(),
+        }
+    }
+
+    /// Translates the lines forward and back. `source_tokenizer` handles the language of the
+    /// input, `target_tokenizer` the other one.
+    pub fn round_trip<K: Tokenizer, L: Tokenizer>(
+        &mut self,
+        source_tokenizer: &K,
+        target_tokenizer: &L,
+        lines: &[String],
+    ) -> Result<Vec<RoundTrip>, String> {
+        let o = &self.options;
+        let mut res = vec![];
+        for chunk in lines.chunks(o.chunk_size.max(1)) {
+            let input = chunk.iter().map(|v| source_tokenizer.encode(v)).collect();
+            let translations: Vec<String> = self
+                .forward
+                .translate_batch(
+                    input,
+                    o.max_batch_size,
+                    o.forward_options.clone(),
+                    o.batch_type,
+                )?
+                .iter()
+                .map(|v| target_tokenizer.decode(v))
+                .collect();
+            if translations.len() != chunk.len() {
+                return Err("forward translator returned a wrong number of results".to_string());
+            }
+            let input = translations
+                .iter()
+                .map(|v| target_tokenizer.encode(v))
+                .collect();
+            let round_trips = self.backward.translate_batch(
+                input,
+                o.max_batch_size,
+                o.backward_options.clone(),
+                o.batch_type,
+            )?;
+            if round_trips.len() != chunk.len() {
+                return Err("backward translator returned a wrong number of results".to_string());
+            }
+            for ((source, translation), round_trip) in
+                chunk.iter().zip(translations).zip(round_trips)
+            {
+                let round_trip = source_tokenizer.decode(&round_trip);
+                res.push(RoundTrip {
+                    chrf: chrf(&round_trip, source),
+                    token_overlap: token_overlap(&round_trip, source),
+                    source: source.clone(),
+                    translation,
+                    round_trip,
+                });
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Writes the `(source, translation)` pairs whose round trip reaches `min_chrf` as two line
+/// aligned files and returns the number of pairs written.
+pub fn write_parallel_corpus(
+    round_trips: &[RoundTrip],
+    source_path: impl AsRef<Path>,
+    target_path: impl AsRef<Path>,
+    min_chrf: f32,
+) -> Result<usize, String> {
+    let create = |path: &Path| {
+        File::create(path)
+            .map(BufWriter::new)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    let mut source = create(source_path.as_ref())?;
+    let mut target = create(target_path.as_ref())?;
+    let mut written = 0;
+    for v in round_trips.iter().filter(|v| v.chrf >= min_chrf) {
+        // Line breaks inside a segment would break the alignment of the files.
+        let line = |text: &str| text.replace(['\r', '\n'], " ");
+        writeln!(source, "{}", line(&v.source)).map_err(|e| e.to_string())?;
+        writeln!(target, "{}", line(&v.translation)).map_err(|e| e.to_string())?;
+        written += 1;
+    }
+    source.flush().map_err(|e| e.to_string())?;
+    target.flush().map_err(|e| e.to_string())?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn round_trips_and_writes_corpus() {
+        let forward = MockTranslator::upper();
+        // Loses the last word on the way back.
+        let backward = MockTranslator::new(|v| {
+            let keep = v.len().saturating_sub(1).max(1);
+            v[..keep].iter().map(|t| t.to_lowercase()).collect()
+        });
+        let mut translator = BackTranslator::new(forward, backward);
+        translator.options.chunk_size = 1;
+        let lines = vec!["hello world".to_string(), "the cat sat down".to_string()];
+        let output = translator
+            .round_trip(&WhitespaceTokenizer, &WhitespaceTokenizer, &lines)
+            .unwrap();
+        assert_eq!(translator.forward.batches.len(), 2);
+        assert_eq!(output[0].translation, "HELLO WORLD");
+        assert_eq!(output[0].round_trip, "hello");
+        assert_eq!(output[1].round_trip, "the cat sat");
+        assert!((output[1].token_overlap - 6.0 / 7.0).abs() < 1e-6);
+        assert!(output[1].chrf > output[0].chrf);
+
+        let dir = std::env::temp_dir().join(format!(
+            "rustyctranslate2-back-translation-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let written =
+            write_parallel_corpus(&output, dir.join("src.txt"), dir.join("tgt.txt"), 0.6).unwrap();
+        assert_eq!(written, 1);
+        assert_eq!(
+            fs::read_to_string(dir.join("tgt.txt")).unwrap(),
+            "THE CAT SAT DOWN\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+//! Length sorted dynamic batching.
+//!
+//! Examples are sorted by length and grouped so that examples of similar length are translated
+//! together, which cuts the padding. The [`Batcher`] does the same across many callers: a worker
+//! thread owns the translator and collects the examples submitted from other threads.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{BatchType, Translate, TranslationOptions, TranslationResult};
+
+/// Splits examples into batches of similar length.
+///
+/// With [`BatchType::Example`] a batch holds at most `max_batch_size` examples, with
+/// [`BatchType::Tokens`] the padded size (longest example times number of examples) is at most
+/// `max_batch_size`. Returns the example indices of every batch, shortest examples first.
+pub fn plan_batches(
+    lengths: &[usize],
+    max_batch_size: usize,
+    batch_type: BatchType,
+) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..lengths.len()).collect();
+    order.sort_by_key(|&i| lengths[i]);
+    let max_batch_size = max_batch_size.max(1);
+    let mut batches: Vec<Vec<usize>> = vec![];
+    let mut current: Vec<usize> = vec![];
+    for i in order {
+        let fits = match batch_type {
+            BatchType::Example => current.len() < max_batch_size,
+            // Sorted ascending, so the new example is the longest of the batch.
+            BatchType::Tokens => lengths[i].max(1) * (current.len() + 1) <= max_batch_size,
+        };
+        if !fits && !current.is_empty() {
+            batches.push(std::mem::take(&mut current));
+        }
+        current.push(i);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+/// Translates a batch in length sorted sub-batches and returns the results in input order.
+pub fn translate_sorted<T: Translate>(
+    translator: &mut T,
+    input: Vec<Vec<String>>,
+    max_batch_size: usize,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<Vec<TranslationResult>, String> {
+    let lengths: Vec<usize> = input.iter().map(|v| v.len()).collect();
+    let mut input: Vec<Option<Vec<String>>> = input.into_iter().map(Some).collect();
+    let mut res: Vec<Option<TranslationResult>> = vec![None; input.len()];
+    for batch in plan_batches(&lengths, max_batch_size, batch_type) {
+        let examples = batch
+            .iter()
+            .map(|&i| input[i].take().unwrap_or_default())
+            .collect();
+        let output = translator.translate_batch_results(
+            examples,
+            None,
+            options.clone(),
+            BatchType::Example,
+        )?;
+        if output.len() != batch.len() {
+            return Err("translator returned a wrong number of results".to_string());
+        }
+        for (i, result) in batch.into_iter().zip(output) {
+            res[i] = Some(result);
+        }
+    }
+    Ok(res.into_iter().flatten().collect())
+}
+
+#[derive(Clone, Debug)]
+pub struct BatcherOptions {
+    /// Batch size limit, in examples or padded tokens depending on `batch_type`.
+    pub max_batch_size: usize,
+    pub batch_type: BatchType,
+    /// How long the worker waits for more examples once it has received one.
+    pub max_wait: Duration,
+    /// The worker stops waiting once this many examples are pending.
+    pub max_pending: usize,
+    pub options: TranslationOptions,
+}
+
+impl Default for BatcherOptions {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 32,
+            batch_type: BatchType::Example,
+            max_wait: Duration::from_millis(5),
+            max_pending: 256,
+            options: TranslationOptions::default(),
+        }
+    }
+}
+
+/// Throughput counters of a [`Batcher`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BatcherStats {
+    pub examples: usize,
+    pub batches: usize,
+    /// Input tokens, without padding.
+    pub tokens: usize,
+    /// Input tokens including the padding to the longest example of every batch.
+    pub padded_tokens: usize,
+    /// Time spent in the translator.
+    pub busy: Duration,
+}
+
+impl BatcherStats {
+    pub fn sentences_per_second(&self) -> f64 {
+        if self.busy.is_zero() {
+            return 0.0;
+        }
+        self.examples as f64 / self.busy.as_secs_f64()
+    }
+
+    /// Fraction of the translated tokens that are padding.
+    pub fn padding_ratio(&self) -> f64 {
+        if self.padded_tokens == 0 {
+            return 0.0;
+        }
+        1.0 - self.tokens as f64 / self.padded_tokens as f64
+    }
+}
+
+type Reply = Sender<Result<Vec<TranslationResult>, String>>;
+
+struct Request {
+    input: Vec<Vec<String>>,
+    reply: Reply,
+}
+
+/// Handle to a translation worker thread. Clones share the worker, which stops once every handle
+/// is dropped.
+#[derive(Clone)]
+pub struct Batcher {
+    sender: Sender<Request>,
+    stats: Arc<Mutex<BatcherStats>>,
+}
+
+impl Batcher {
+    pub fn new<T: Translate + Send + 'static>(translator: T, options: BatcherOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let stats = Arc::new(Mutex::new(BatcherStats::default()));
+        let worker_stats = stats.clone();
+        thread::spawn(move || run(translator, options, receiver, worker_stats));
+        Self { sender, stats }
+    }
+
+    /// Translates the examples of one caller, blocking until they are done.
+    pub fn translate(&self, input: Vec<Vec<String>>) -> Result<Vec<TranslationResult>, String> {
+        if input.is_empty() {
+            return Ok(vec![]);
+        }
+        let (reply, receiver) = mpsc::channel();
+        self.sender
+            .send(Request { input, reply })
+            .map_err(|_| "batcher worker stopped".to_string())?;
+        receiver
+            .recv()
+            .map_err(|_| "batcher worker stopped".to_string())?
+    }
+
+    pub fn stats(&self) -> BatcherStats {
+        *self.stats.lock().unwrap()
+    }
+}
+
+fn run<T: Translate>(
+    mut translator: T,
+    options: BatcherOptions,
+    receiver: Receiver<Request>,
+    stats: Arc<Mutex<BatcherStats>>,
+) {
+    while let Ok(first) = receiver.recv() {
+        let mut requests = vec![first];
+        let mut pending = requests[0].input.len();
+        let deadline = Instant::now() + options.max_wait;
+        while pending < options.max_pending {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(request) => {
+                    pending += request.input.len();
+                    requests.push(request);
+                }
+                Err(_) => break,
+            }
+        }
+
+        // (request, position in the request) of every collected example.
+        let mut origin = vec![];
+        let mut examples = vec![];
+        let mut results: Vec<Vec<Option<TranslationResult>>> = vec![];
+        for (r, request) in requests.iter_mut().enumerate() {
+            results.push(vec![None; request.input.len()]);
+            for (i, example) in std::mem::take(&mut request.input).into_iter().enumerate() {
+                origin.push((r, i));
+                examples.push(example);
+            }
+        }
+        let mut failed: Vec<Option<String>> = vec![None; requests.len()];
+
+        let lengths: Vec<usize> = examples.iter().map(|v| v.len()).collect();
+        let mut examples: Vec<Option<Vec<String>>> = examples.into_iter().map(Some).collect();
+        for batch in plan_batches(&lengths, options.max_batch_size, options.batch_type) {
+            let input = batch
+                .iter()
+                .map(|&i| examples[i].take().unwrap_or_default())
+                .collect();
+            let start = Instant::now();
+            let output = translator.translate_batch_results(
+                input,
+                None,
+                Some(options.options.clone()),
+                BatchType::Example,
+            );
+            {
+                let mut stats = stats.lock().unwrap();
+                let longest = batch.iter().map(|&i| lengths[i]).max().unwrap_or(0);
+                stats.examples += batch.len();
+                stats.batches += 1;
+                stats.tokens += batch.iter().map(|&i| lengths[i]).sum::<usize>();
+                stats.padded_tokens += longest * batch.len();
+                stats.busy += start.elapsed();
+            }
+            match output {
+                Ok(output) if output.len() == batch.len() => {
+                    for (i, result) in batch.into_iter().zip(output) {
+                        let (r, position) = origin[i];
+                        results[r][position] = Some(result);
+                    }
+                }
+                other => {
+                    let error = other.err().unwrap_or_else(|| {
+                        "translator returned a wrong number of results".to_string()
+                    });
+                    for i in batch {
+                        failed[origin[i].0] = Some(error.clone());
+                    }
+                }
+            }
+        }
+
+        for ((request, results), failed) in requests.into_iter().zip(results).zip(failed) {
+            let reply = match failed {
+                Some(error) => Err(error),
+                None => Ok(results.into_iter().flatten().collect()),
+            };
+            // The caller may have given up waiting.
+            let _ = request.reply.send(reply);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+
+    #[test]
+    fn plans_sorted_batches() {
+        let lengths = [5, 1, 3, 2, 8];
+        assert_eq!(
+            plan_batches(&lengths, 2, BatchType::Example),
+            vec![vec![1, 3], vec![2, 0], vec![4]]
+        );
+        assert_eq!(
+            plan_batches(&lengths, 9, BatchType::Tokens),
+            vec![vec![1, 3, 2], vec![0], vec![4]]
+        );
+
+        let mut translator = MockTranslator::upper();
+        let input: Vec<Vec<String>> = ["a b c", "d", "e f"]
+            .iter()
+            .map(|v| v.split(' ').map(|t| t.to_string()).collect())
+            .collect();
+        let output = translate_sorted(&mut translator, input, 2, None, BatchType::Example).unwrap();
+        let output: Vec<String> = output.iter().map(|v| v.output().join(" ")).collect();
+        assert_eq!(output, vec!["A B C", "D", "E F"]);
+        assert_eq!(translator.batches.len(), 2);
+    }
+
+    #[test]
+    fn batches_across_callers() {
+        let options = BatcherOptions {
+            max_wait: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let batcher = Batcher::new(MockTranslator::upper(), options);
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let batcher = batcher.clone();
+                thread::spawn(move || {
+                    let input = vec![vec![format!("x{}", i)], vec!["y".to_string(); i + 1]];
+                    batcher.translate(input).unwrap()
+                })
+            })
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            let output = handle.join().unwrap();
+            assert_eq!(output[0].output(), [format!("X{}", i)]);
+            assert_eq!(output[1].output().len(), i + 1);
+        }
+        let stats = batcher.stats();
+        // How many callers share a batch depends on the scheduling, the totals do not.
+        assert_eq!(stats.examples, 8);
+        assert_eq!(stats.tokens, 14);
+        assert!((1..=8).contains(&stats.batches));
+        assert!(stats.padded_tokens >= stats.tokens);
+    }
+}
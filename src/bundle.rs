@@ -0,0 +1,540 @@
+//! JSON and YAML resource bundles (i18next, Rails style).
+//!
+//! Only the string leaves are translated. ICU MessageFormat arguments are kept: simple arguments
+//! and interpolations (`{name}`, `{{name}}`, `%{name}`) are placeholders, and the options of
+//! `plural`, `select` and `selectordinal` arguments are translated one by one.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::localization::{translate_texts, LocalizationReport};
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BundleFormat {
+    Json,
+    Yaml,
+}
+
+impl BundleFormat {
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|v| v.to_str()) {
+            Some("json") => Ok(BundleFormat::Json),
+            Some("yml" | "yaml") => Ok(BundleFormat::Yaml),
+            _ => Err(format!("{}: unknown bundle format", path.display())),
+        }
+    }
+}
+
+/// A resource bundle. YAML needs the `yaml` feature; its comments and anchors are not kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bundle {
+    pub format: BundleFormat,
+    pub root: Value,
+}
+
+impl Bundle {
+    pub fn parse(text: &str, format: BundleFormat) -> Result<Self, String> {
+        let root = match format {
+            BundleFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string())?,
+            BundleFormat::Yaml => parse_yaml(text)?,
+        };
+        Ok(Self { format, root })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&text, BundleFormat::from_path(path)?)
+    }
+
+    pub fn render(&self) -> Result<String, String> {
+        match self.format {
+            BundleFormat::Json => serde_json::to_string_pretty(&self.root)
+                .map(|v| v + "\n")
+                .map_err(|e| e.to_string()),
+            BundleFormat::Yaml => render_yaml(&self.root),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        fs::write(path, self.render()?).map_err(|e| e.to_string())
+    }
+
+    /// Renames a top-level key, keeping its position. Rails bundles have the locale as top-level
+    /// key (`en:`), rename it to the target locale before translating.
+    pub fn rename_locale(&mut self, from: &str, to: &str) {
+        if let Value::Object(map) = &mut self.root {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(k, v)| {
+                    if k == from {
+                        (to.to_string(), v)
+                    } else {
+                        (k, v)
+                    }
+                })
+                .collect();
+        }
+    }
+}
+
+/// Translates the string leaves of `source` missing from `existing` (or empty there), in one
+/// batch for the whole bundle.
+///
+/// The output has the keys of `source` in their order, with the values of `existing` where
+/// present, followed by the keys only found in `existing`.
+pub fn translate_bundle<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    source: &Bundle,
+    existing: Option<&Bundle>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<(Bundle, LocalizationReport), String> {
+    let existing = existing.map(|v| &v.root);
+    let mut pending = vec![];
+    collect(&source.root, existing, String::new(), &mut pending);
+
+    let mut report = LocalizationReport::default();
+    let mut messages: Vec<Message> = pending
+        .iter()
+        .map(|(key, text)| {
+            Message::parse(text).unwrap_or_else(|_| {
+                report.invalid.push(key.clone());
+                Message {
+                    text: text.to_string(),
+                    args: vec![],
+                }
+            })
+        })
+        .collect();
+    let texts: Vec<String> = messages
+        .iter_mut()
+        .flat_map(|v| v.texts_mut())
+        .map(|v| v.clone())
+        .collect();
+    let mut translations = translate_texts(
+        translator,
+        tokenizer,
+        &texts,
+        max_batch_size,
+        options,
+        batch_type,
+    )?
+    .into_iter();
+    for ((key, _), message) in pending.iter().zip(&mut messages) {
+        let mut valid = true;
+        for text in message.texts_mut() {
+            let translation = translations.next().unwrap_or_default();
+            valid &= translation.is_valid();
+            *text = translation.text;
+        }
+        if !valid && !report.invalid.contains(key) {
+            report.invalid.push(key.clone());
+        }
+    }
+    report.translated = messages.len();
+
+    let mut rendered = messages.iter().map(|v| v.render());
+    let root = merge(&source.root, existing, &mut rendered);
+    let bundle = Bundle {
+        format: source.format,
+        root,
+    };
+    Ok((bundle, report))
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(text: &str) -> Result<Value, String> {
+    serde_yaml::from_str(text).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "yaml")]
+fn render_yaml(root: &Value) -> Result<String, String> {
+    serde_yaml::to_string(root).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "yaml"))]
+fn parse_yaml(_: &str) -> Result<Value, String> {
+    Err("YAML bundles need the `yaml` feature".to_string())
+}
+
+#[cfg(not(feature = "yaml"))]
+fn render_yaml(_: &Value) -> Result<String, String> {
+    Err("YAML bundles need the `yaml` feature".to_string())
+}
+
+/// Whether a value of the target bundle is kept instead of translating the source.
+fn is_present(existing: Option<&Value>) -> bool {
+    existing.is_some_and(|v| v.as_str() != Some(""))
+}
+
+/// String leaves to translate with their dotted keys, in document order.
+fn collect<'a>(
+    source: &'a Value,
+    existing: Option<&Value>,
+    key: String,
+    out: &mut Vec<(String, &'a str)>,
+) {
+    let child = |name: &str| {
+        if key.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", key, name)
+        }
+    };
+    match source {
+        Value::Object(map) => {
+            for (name, value) in map {
+                collect(value, existing.and_then(|v| v.get(name)), child(name), out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                let existing = existing.and_then(|v| v.get(i));
+                collect(value, existing, child(&i.to_string()), out);
+            }
+        }
+        Value::String(text) if !is_present(existing) => out.push((key, text)),
+        _ => {}
+    }
+}
+
+/// Builds the target bundle in the order of [`collect`], taking the translations from `rendered`.
+fn merge(
+    source: &Value,
+    existing: Option<&Value>,
+    rendered: &mut impl Iterator<Item = String>,
+) -> Value {
+    match source {
+        Value::Object(map) => {
+            let mut out = Map::new();
+            for (name, value) in map {
+                let value = merge(value, existing.and_then(|v| v.get(name)), rendered);
+                out.insert(name.clone(), value);
+            }
+            if let Some(Value::Object(existing)) = existing {
+                for (name, value) in existing {
+                    if !out.contains_key(name) {
+                        out.insert(name.clone(), value.clone());
+                    }
+                }
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, value)| merge(value, existing.and_then(|v| v.get(i)), rendered))
+                .collect(),
+        ),
+        Value::String(_) if !is_present(existing) => {
+            Value::String(rendered.next().unwrap_or_default())
+        }
+        _ => existing.unwrap_or(source).clone(),
+    }
+}
+
+/// An ICU MessageFormat message: the text with every argument replaced by a `{name}` placeholder,
+/// and the arguments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Message {
+    text: String,
+    args: Vec<(String, Argument)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Argument {
+    /// Kept as written, e.g. `{n, number}`, `{{name}}` or the `#` of plural options.
+    Simple(String),
+    /// `plural`, `select` or `selectordinal`: the head (`count, plural, offset:1`) and the options.
+    Choice(String, Vec<(String, Message)>),
+}
+
+impl Message {
+    fn parse(text: &str) -> Result<Self, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut pos = 0;
+        let message = parse_message(&chars, &mut pos, false, false)?;
+        if pos < chars.len() {
+            return Err(format!("unmatched }} at {}", pos));
+        }
+        Ok(message)
+    }
+
+    /// Texts to translate: the message and the options of its arguments.
+    fn texts_mut(&mut self) -> Vec<&mut String> {
+        let mut res = vec![&mut self.text];
+        for (_, argument) in &mut self.args {
+            if let Argument::Choice(_, options) = argument {
+                for (_, message) in options {
+                    res.extend(message.texts_mut());
+                }
+            }
+        }
+        res
+    }
+
+    fn render(&self) -> String {
+        let mut out = self.text.clone();
+        for (token, argument) in &self.args {
+            let value = match argument {
+                Argument::Simple(text) => text.clone(),
+                Argument::Choice(head, options) => {
+                    let options: String = options
+                        .iter()
+                        .map(|(selector, message)| {
+                            format!(" {} {{{}}}", selector, message.render())
+                        })
+                        .collect();
+                    format!("{{{}{}}}", head, options)
+                }
+            };
+            out = out.replace(token, &value);
+        }
+        out
+    }
+
+    /// Adds an argument and its placeholder to the text.
+    fn push_argument(&mut self, name: &str, argument: Argument) {
+        let mut token = format!("{{{}}}", name);
+        let mut n = 1;
+        while let Some((_, other)) = self.args.iter().find(|(t, _)| *t == token) {
+            if *other == argument {
+                break;
+            }
+            n += 1;
+            token = format!("{{{}_{}}}", name, n);
+        }
+        self.text.push_str(&token);
+        if !self.args.iter().any(|(t, _)| *t == token) {
+            self.args.push((token, argument));
+        }
+    }
+}
+
+/// Parses up to the end of the text, or to the `}` closing a nested message.
+fn parse_message(
+    chars: &[char],
+    pos: &mut usize,
+    nested: bool,
+    plural: bool,
+) -> Result<Message, String> {
+    let mut message = Message::default();
+    let find = |from: usize, pattern: &[char]| {
+        (from..chars.len()).find(|&i| chars[i..].starts_with(pattern))
+    };
+    while let Some(&c) = chars.get(*pos) {
+        let next = chars.get(*pos + 1).copied();
+        match c {
+            '}' if nested => return Ok(message),
+            // i18next `{{name}}` and Rails `%{name}` interpolations.
+            '{' | '%' if next == Some('{') => {
+                let close: &[char] = if c == '{' { &['}', '}'] } else { &['}'] };
+                let end = find(*pos, close).ok_or("unclosed interpolation")? + close.len();
+                let original: String = chars[*pos..end].iter().collect();
+                let name = original.trim_matches(['{', '}', '%', ' ']).to_string();
+                message.push_argument(&name, Argument::Simple(original));
+                *pos = end;
+            }
+            '{' => {
+                *pos += 1;
+                let (name, argument) = parse_argument(chars, pos)?;
+                message.push_argument(&name, argument);
+            }
+            '#' if plural => {
+                message.push_argument("#", Argument::Simple("#".to_string()));
+                *pos += 1;
+            }
+            c => {
+                message.text.push(c);
+                *pos += 1;
+            }
+        }
+    }
+    if nested {
+        return Err("unclosed {".to_string());
+    }
+    Ok(message)
+}
+
+/// Parses an argument after its `{`, up to and including its `}`.
+fn parse_argument(chars: &[char], pos: &mut usize) -> Result<(String, Argument), String> {
+    let start = *pos - 1;
+    let word = |pos: &mut usize| {
+        let from = *pos;
+        while chars
+            .get(*pos)
+            .is_some_and(|c| !matches!(c, ',' | '}' | '{'))
+        {
+            *pos += 1;
+        }
+        chars[from..*pos]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
+    let name = word(pos);
+    let kind = if chars.get(*pos) == Some(&',') {
+        *pos += 1;
+        word(pos)
+    } else {
+        String::new()
+    };
+    if !matches!(kind.as_str(), "plural" | "select" | "selectordinal") {
+        // Simple argument, possibly with a style containing braces.
+        let mut depth = 1;
+        while depth > 0 {
+            match chars.get(*pos) {
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some(_) => {}
+                None => return Err("unclosed {".to_string()),
+            }
+            *pos += 1;
+        }
+        let original = chars[start..*pos].iter().collect();
+        return Ok((name, Argument::Simple(original)));
+    }
+
+    if chars.get(*pos) != Some(&',') {
+        return Err(format!("{} argument without options", kind));
+    }
+    *pos += 1;
+    let mut head = format!("{}, {},", name, kind);
+    let mut options = vec![];
+    loop {
+        while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+            *pos += 1;
+        }
+        match chars.get(*pos) {
+            Some('}') => {
+                *pos += 1;
+                break;
+            }
+            None => return Err("unclosed {".to_string()),
+            _ => {}
+        }
+        let from = *pos;
+        while chars
+            .get(*pos)
+            .is_some_and(|c| !c.is_whitespace() && *c != '{' && *c != '}')
+        {
+            *pos += 1;
+        }
+        let selector: String = chars[from..*pos].iter().collect();
+        if selector.starts_with("offset:") {
+            head.push(' ');
+            head.push_str(&selector);
+            continue;
+        }
+        while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+            *pos += 1;
+        }
+        if chars.get(*pos) != Some(&'{') {
+            return Err(format!("expected {{ after {}", selector));
+        }
+        *pos += 1;
+        let message = parse_message(chars, pos, true, kind != "select")?;
+        *pos += 1;
+        options.push((selector, message));
+    }
+    Ok((name, Argument::Choice(head, options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn parses_icu_messages() {
+        let text =
+            "{count, plural, offset:1 =0 {no items} one {# item} other {# items}} for {name}";
+        let message = Message::parse(text).unwrap();
+        assert_eq!(message.text, "{count} for {name}");
+        assert_eq!(
+            message.render(),
+            "{count, plural, offset:1 =0 {no items} one {# item} other {# items}} for {name}"
+        );
+        let message =
+            Message::parse("{g, select, male {He} other {They}} has {{n}} %{x} {n, number}")
+                .unwrap();
+        assert_eq!(message.text, "{g} has {n} {x} {n_2}");
+        assert!(Message::parse("{broken").is_err());
+    }
+
+    #[test]
+    fn translates_bundles() {
+        let source = Bundle::parse(
+            r#"{"title": "hello world", "nav": {"home": "the home", "items": "{count, plural, one {# item} other {# items}}"}, "list": ["a", "b"], "size": 3}"#,
+            BundleFormat::Json,
+        )
+        .unwrap();
+        let existing = Bundle::parse(
+            r#"{"nav": {"home": "Startseite", "extra": "bleibt"}, "list": [""]}"#,
+            BundleFormat::Json,
+        )
+        .unwrap();
+        let mut translator = MockTranslator::upper();
+        let (output, report) = translate_bundle(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &source,
+            Some(&existing),
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(report.translated, 4);
+        assert!(report.invalid.is_empty());
+        assert_eq!(translator.batches.len(), 1);
+        assert_eq!(
+            output.render().unwrap(),
+            r#"{
+  "title": "HELLO WORLD",
+  "nav": {
+    "home": "Startseite",
+    "items": "{count, plural, one {# ITEM} other {# ITEMS}}",
+    "extra": "bleibt"
+  },
+  "list": [
+    "A",
+    "B"
+  ],
+  "size": 3
+}
+"#
+        );
+        #[cfg(not(feature = "yaml"))]
+        assert!(Bundle::parse("en:\n  hi: hello\n", BundleFormat::Yaml).is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn translates_yaml_bundles() {
+        let mut translator = MockTranslator::upper();
+        let mut rails = Bundle::parse("en:\n  hi: hello %{name}\n", BundleFormat::Yaml).unwrap();
+        rails.rename_locale("en", "de");
+        let (output, _) = translate_bundle(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &rails,
+            None,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(output.render().unwrap(), "de:\n  hi: HELLO %{name}\n");
+    }
+}
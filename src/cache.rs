@@ -0,0 +1,485 @@
+//! LRU cache in front of a [`Translate`] backend.
+//!
+//! Entries are keyed by the model identity, the translation options and the input tokens, so a
+//! batch is split into cached examples and misses and only the misses reach the translator.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{BatchType, Translate, TranslationOptions, TranslationResult};
+
+/// Distinguishes the temporary files of the disk cache written by one process.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Debug)]
+pub struct CacheOptions {
+    /// Maximum number of entries kept in memory.
+    pub capacity: usize,
+    /// Entries older than this are translated again.
+    pub ttl: Option<Duration>,
+    /// Directory used as a second, unbounded cache level shared between processes.
+    pub disk: Option<PathBuf>,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            ttl: None,
+            disk: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Failed writes to the disk cache; the translations are still returned.
+    pub disk_errors: usize,
+}
+
+struct Entry {
+    key: String,
+    result: TranslationResult,
+    created: Instant,
+    used: u64,
+}
+
+pub struct CachedTranslator<T: Translate> {
+    inner: T,
+    model_id: String,
+    options: CacheOptions,
+    entries: HashMap<u64, Entry>,
+    /// Last use tick -> hash, the first entry is the least recently used.
+    usage: BTreeMap<u64, u64>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl<T: Translate> CachedTranslator<T> {
+    /// `model_id` identifies the model (e.g. its path and version) in the cache keys.
+    pub fn new(inner: T, model_id: &str, options: CacheOptions) -> Result<Self, String> {
+        if let Some(dir) = &options.disk {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        Ok(Self {
+            inner,
+            model_id: model_id.to_string(),
+            options,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        })
+    }
+
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.usage.clear();
+    }
+
+    fn key(&self, tokens: &[String], options: &TranslationOptions) -> String {
+        // Every option changes the output, so the whole struct is part of the key.
+        let mut key = format!("{}\n{:?}\n", self.model_id, options);
+        for token in tokens {
+            key.push_str(&escape(token));
+            key.push('\t');
+        }
+        key
+    }
+
+    fn get(&mut self, key: &str) -> Option<TranslationResult> {
+        let hash = fnv1a(key);
+        let expired =
+            |created: Instant, ttl: Option<Duration>| ttl.is_some_and(|t| created.elapsed() > t);
+        if let Some(entry) = self.entries.get_mut(&hash) {
+            if entry.key == key && !expired(entry.created, self.options.ttl) {
+                self.usage.remove(&entry.used);
+                self.tick += 1;
+                entry.used = self.tick;
+                self.usage.insert(self.tick, hash);
+                return Some(entry.result.clone());
+            }
+            if let Some(entry) = self.entries.remove(&hash) {
+                self.usage.remove(&entry.used);
+            }
+        }
+        let result = self.read_disk(hash, key)?;
+        self.put(key, result.clone());
+        Some(result)
+    }
+
+    fn put(&mut self, key: &str, result: TranslationResult) {
+        if self.options.capacity == 0 {
+            return;
+        }
+        let hash = fnv1a(key);
+        if let Some(entry) = self.entries.remove(&hash) {
+            self.usage.remove(&entry.used);
+        }
+        while self.entries.len() >= self.options.capacity {
+            let Some((_, oldest)) = self.usage.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.usage.insert(self.tick, hash);
+        self.entries.insert(
+            hash,
+            Entry {
+                key: key.to_string(),
+                result,
+                created: Instant::now(),
+                used: self.tick,
+            },
+        );
+    }
+
+    fn read_disk(&self, hash: u64, key: &str) -> Option<TranslationResult> {
+        let path = self.options.disk.as_ref()?.join(format!("{:016x}", hash));
+        if let Some(ttl) = self.options.ttl {
+            let modified = fs::metadata(&path).ok()?.modified().ok()?;
+            if SystemTime::now().duration_since(modified).ok()? > ttl {
+                return None;
+            }
+        }
+        let text = fs::read_to_string(path).ok()?;
+        let (stored_key, result) = decode_entry(&text)?;
+        (stored_key == key).then_some(result)
+    }
+
+    fn write_disk(&self, key: &str, result: &TranslationResult) -> Result<(), String> {
+        let Some(dir) = &self.options.disk else {
+            return Ok(());
+        };
+        let path = dir.join(format!("{:016x}", fnv1a(key)));
+        // Unique per writer, so processes sharing the directory never rename a torn file.
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let res = fs::write(&tmp, encode_entry(key, result)).and_then(|_| fs::rename(&tmp, path));
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        res.map_err(|e| e.to_string())
+    }
+}
+
+impl<T: Translate> Translate for CachedTranslator<T> {
+    fn translate_batch_results(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<TranslationResult>, String> {
+        let options = options.unwrap_or_default();
+        let keys: Vec<String> = input.iter().map(|v| self.key(v, &options)).collect();
+        let mut res: Vec<Option<TranslationResult>> = keys.iter().map(|k| self.get(k)).collect();
+
+        // Identical misses are translated once, the copies count as hits.
+        let mut misses: Vec<usize> = vec![];
+        let mut first_miss: HashMap<&str, usize> = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            if res[i].is_none() {
+                first_miss.entry(key.as_str()).or_insert_with(|| {
+                    misses.push(i);
+                    i
+                });
+            }
+        }
+        self.stats.hits += input.len() - misses.len();
+        self.stats.misses += misses.len();
+
+        if !misses.is_empty() {
+            let batch = misses.iter().map(|&i| input[i].clone()).collect();
+            let output = self.inner.translate_batch_results(
+                batch,
+                max_batch_size,
+                Some(options),
+                batch_type,
+            )?;
+            if output.len() != misses.len() {
+                return Err("translator returned a wrong number of results".to_string());
+            }
+            for (&i, result) in misses.iter().zip(output) {
+                // The disk tier is only a cache, a failed write must not lose the translation.
+                if self.write_disk(&keys[i], &result).is_err() {
+                    self.stats.disk_errors += 1;
+                }
+                self.put(&keys[i], result.clone());
+                res[i] = Some(result);
+            }
+            for i in 0..res.len() {
+                if res[i].is_none() {
+                    res[i] = res[first_miss[keys[i].as_str()]].clone();
+                }
+            }
+        }
+        Ok(res.into_iter().flatten().collect())
+    }
+}
+
+/// 64 bit FNV-1a, stable across processes for the disk cache file names.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Line based entry format: the key, then `h`ypotheses, `s`cores and `a`ttention rows.
+fn encode_entry(key: &str, result: &TranslationResult) -> String {
+    let join = |values: Vec<String>| values.join("\t");
+    let mut out = format!("k\t{}\n", escape(key));
+    for hypothesis in &result.hypotheses {
+        out.push_str(&format!(
+            "h\t{}\n",
+            join(hypothesis.iter().map(|v| escape(v)).collect())
+        ));
+    }
+    out.push_str(&format!(
+        "s\t{}\n",
+        join(result.scores.iter().map(f32::to_string).collect())
+    ));
+    for (i, attention) in result.attention.iter().enumerate() {
+        for row in attention {
+            out.push_str(&format!(
+                "a\t{}\t{}\n",
+                i,
+                join(row.iter().map(f32::to_string).collect())
+            ));
+        }
+    }
+    out
+}
+
+fn fields(line: &str) -> Vec<&str> {
+    if line.is_empty() {
+        vec![]
+    } else {
+        line.split('\t').collect()
+    }
+}
+
+fn decode_entry(text: &str) -> Option<(String, TranslationResult)> {
+    let mut key = None;
+    let mut result = TranslationResult::default();
+    for line in text.lines() {
+        let (kind, rest) = line.split_once('\t').unwrap_or((line, ""));
+        match kind {
+            "k" => key = Some(unescape(rest)),
+            "h" => result
+                .hypotheses
+                .push(fields(rest).into_iter().map(unescape).collect()),
+            "s" => {
+                result.scores = fields(rest)
+                    .into_iter()
+                    .map(|v| v.parse().ok())
+                    .collect::<Option<_>>()?
+            }
+            "a" => {
+                let (index, row) = rest.split_once('\t').unwrap_or((rest, ""));
+                let index: usize = index.parse().ok()?;
+                let row = fields(row)
+                    .into_iter()
+                    .map(|v| v.parse().ok())
+                    .collect::<Option<_>>()?;
+                if result.attention.len() <= index {
+                    result.attention.resize(index + 1, vec![]);
+                }
+                result.attention[index].push(row);
+            }
+            _ => return None,
+        }
+    }
+    // Hypotheses without attention rows still get their (empty) matrix.
+    if !result.attention.is_empty() {
+        result
+            .attention
+            .resize(result.hypotheses.len().max(result.attention.len()), vec![]);
+    }
+    Some((key?, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+
+    fn tokens(text: &str) -> Vec<String> {
+        text.split(' ').map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn serves_hits_and_evicts() {
+        let options = CacheOptions {
+            capacity: 2,
+            ..Default::default()
+        };
+        let mut cache = CachedTranslator::new(MockTranslator::upper(), "m", options).unwrap();
+        let output = cache
+            .translate_batch(
+                vec![tokens("a b"), tokens("c"), tokens("a b")],
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(output, vec![tokens("A B"), tokens("C"), tokens("A B")]);
+        assert_eq!(
+            cache.inner().batches,
+            vec![vec![tokens("a b"), tokens("c")]]
+        );
+
+        // "c" is the least recently used entry and gets evicted by "d".
+        cache
+            .translate_batch(
+                vec![tokens("a b"), tokens("d")],
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        cache
+            .translate_batch(
+                vec![tokens("c"), tokens("a b")],
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(cache.inner().translated(), 4);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 4,
+                disk_errors: 0
+            }
+        );
+
+        // Other options are other entries.
+        cache
+            .translate_batch(
+                vec![tokens("c")],
+                None,
+                Some(TranslationOptions::greedy()),
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(cache.inner().translated(), 5);
+    }
+
+    #[test]
+    fn persists_on_disk() {
+        let dir =
+            std::env::temp_dir().join(format!("rustyctranslate2-cache-{}", std::process::id()));
+        let options = CacheOptions {
+            disk: Some(dir.clone()),
+            ..Default::default()
+        };
+        let input = vec![tokens("x\ty \\z")];
+        let translation_options = TranslationOptions {
+            return_scores: true,
+            return_attention: true,
+            ..Default::default()
+        };
+        let mut first =
+            CachedTranslator::new(MockTranslator::echo(), "m", options.clone()).unwrap();
+        let expected = first
+            .translate_batch_results(
+                input.clone(),
+                None,
+                Some(translation_options.clone()),
+                BatchType::Example,
+            )
+            .unwrap();
+
+        let mut second = CachedTranslator::new(MockTranslator::echo(), "m", options).unwrap();
+        let output = second
+            .translate_batch_results(input, None, Some(translation_options), BatchType::Example)
+            .unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(second.inner().translated(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn survives_disk_errors() {
+        let dir = std::env::temp_dir().join(format!(
+            "rustyctranslate2-cache-readonly-{}",
+            std::process::id()
+        ));
+        let options = CacheOptions {
+            disk: Some(dir.clone()),
+            ..Default::default()
+        };
+        let mut cache = CachedTranslator::new(MockTranslator::upper(), "m", options).unwrap();
+        // A file in place of the directory makes every write fail, even for root.
+        fs::remove_dir_all(&dir).unwrap();
+        fs::write(&dir, "").unwrap();
+        let output = cache
+            .translate_batch(
+                vec![tokens("a b"), tokens("c")],
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(output, vec![tokens("A B"), tokens("C")]);
+        assert_eq!(cache.stats().disk_errors, 2);
+        assert_eq!(cache.len(), 2);
+        fs::remove_file(dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! Batch translation with one result per example, so a bad example does not fail its batch.
+
+use std::fmt;
+
+use crate::retry::translate_bisecting;
+use crate::{BatchType, Translate, TranslationOptions, TranslationResult};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExampleError {
+    /// The example has no tokens (or only empty ones).
+    Empty,
+    /// The example is longer than the allowed length.
+    TooLong { length: usize, max_length: usize },
+    /// The translator failed on this example alone.
+    Translation(String),
+}
+
+impl fmt::Display for ExampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExampleError::Empty => write!(f, "empty example"),
+            ExampleError::TooLong { length, max_length } => write!(
+                f,
+                "example has {} tokens, the maximum is {}",
+                length, max_length
+            ),
+            ExampleError::Translation(e) => write!(f, "translation failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExampleError {}
+
+/// Rust side checks of an example.
+///
+/// `max_length` only applies when truncation is off (`max_input_length` is 0): longer examples
+/// are rejected instead of being passed to the model.
+pub fn check_example(
+    tokens: &[String],
+    options: &TranslationOptions,
+    max_length: Option<usize>,
+) -> Result<(), ExampleError> {
+    if tokens.iter().all(|v| v.trim().is_empty()) {
+        return Err(ExampleError::Empty);
+    }
+    match max_length {
+        Some(max_length) if options.max_input_length == 0 && tokens.len() > max_length => {
+            Err(ExampleError::TooLong {
+                length: tokens.len(),
+                max_length,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Translates the examples passing [`check_example`] and returns one result per input example,
+/// in input order.
+///
+/// If the translator fails, the batch is split until the failing examples are isolated, so only
+/// they get an [`ExampleError::Translation`].
+pub fn translate_batch_checked<T: Translate>(
+    translator: &mut T,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+    max_length: Option<usize>,
+) -> Vec<Result<TranslationResult, ExampleError>> {
+    let checks = options.clone().unwrap_or_default();
+    let mut res: Vec<Result<TranslationResult, ExampleError>> = vec![];
+    let mut valid = vec![];
+    let mut examples = vec![];
+    for (i, example) in input.into_iter().enumerate() {
+        match check_example(&example, &checks, max_length) {
+            Ok(()) => {
+                valid.push(i);
+                examples.push(example);
+                res.push(Ok(TranslationResult::default()));
+            }
+            Err(e) => res.push(Err(e)),
+        }
+    }
+    let output = translate_bisecting(
+        translator,
+        examples,
+        max_batch_size,
+        options,
+        batch_type,
+        |_| true,
+    );
+    for (i, result) in valid.into_iter().zip(output) {
+        res[i] = result.map_err(ExampleError::Translation);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+
+    #[test]
+    fn reports_errors_per_example() {
+        let mut translator = MockTranslator::echo();
+        translator.fail_on = Some("crash".to_string());
+        let tokens = |v: &[&str]| v.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let input = vec![
+            tokens(&["a"]),
+            tokens(&[]),
+            tokens(&["a", "b", "c"]),
+            tokens(&["crash"]),
+            tokens(&["b"]),
+        ];
+        let options = TranslationOptions {
+            max_input_length: 0,
+            ..Default::default()
+        };
+        let output = translate_batch_checked(
+            &mut translator,
+            input,
+            None,
+            Some(options),
+            BatchType::Example,
+            Some(2),
+        );
+        assert_eq!(output[0].as_ref().unwrap().output(), ["a"]);
+        assert_eq!(output[1], Err(ExampleError::Empty));
+        assert_eq!(
+            output[2],
+            Err(ExampleError::TooLong {
+                length: 3,
+                max_length: 2
+            })
+        );
+        assert_eq!(
+            output[3],
+            Err(ExampleError::Translation(
+                "unsupported token crash".to_string()
+            ))
+        );
+        assert_eq!(output[4].as_ref().unwrap().output(), ["b"]);
+    }
+}
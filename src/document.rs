@@ -0,0 +1,910 @@
+//! Markdown and HTML documents.
+//!
+//! Only the text is translated: code blocks, code spans, URLs, link destinations and HTML attributes
+//! are kept as written. Inline formatting (emphasis, links, inline elements) is passed to
+//! [`translate_markup`](crate::markup::translate_markup) as tags, so it follows the words it wraps.
+
+use crate::localization::{join_inline, split_inline, translate_texts, LocalizationReport};
+use crate::markup;
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+/// HTML elements that do not end a run of text.
+const INLINE_ELEMENTS: [&str; 26] = [
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "data", "del", "dfn", "em", "i", "img", "ins",
+    "label", "mark", "q", "s", "small", "span", "strong", "sub", "sup", "time", "u", "wbr",
+];
+/// Inline HTML elements whose content is kept as written.
+const CODE_ELEMENTS: [&str; 4] = ["code", "kbd", "samp", "var"];
+/// Block HTML elements whose content is kept as written.
+const VERBATIM_ELEMENTS: [&str; 6] = ["math", "pre", "script", "style", "svg", "textarea"];
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Translates the text of a Markdown (CommonMark) document, in one batch for the whole document.
+///
+/// Paragraphs spread over several lines are written back on one line.
+pub fn translate_markdown<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    text: &str,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<(String, LocalizationReport), String> {
+    let mut document = Document::default();
+    parse_markdown(text, &mut document);
+    document.translate(translator, tokenizer, max_batch_size, options, batch_type)
+}
+
+/// Translates the text nodes of an HTML document, in one batch for the whole document.
+///
+/// Elements with `translate="no"` or the `notranslate` class are kept as written.
+pub fn translate_html<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    text: &str,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<(String, LocalizationReport), String> {
+    let mut document = Document::default();
+    parse_html(text, &mut document);
+    document.translate(translator, tokenizer, max_batch_size, options, batch_type)
+}
+
+/// A document split into text kept as written and units to translate.
+#[derive(Default)]
+struct Document {
+    pieces: Vec<Piece>,
+    units: Vec<Unit>,
+}
+
+enum Piece {
+    Raw(String),
+    Unit(usize),
+}
+
+struct Unit {
+    source: String,
+    /// Text to translate, with the kept parts replaced by tags.
+    text: String,
+    /// Tags standing for kept parts, and the parts.
+    kept: Vec<(String, String)>,
+    /// Inline tags of HTML units, whose text is escaped again after translation.
+    html_codes: Option<Vec<String>>,
+}
+
+impl Document {
+    fn raw(&mut self, text: &str) {
+        match self.pieces.last_mut() {
+            Some(Piece::Raw(last)) => last.push_str(text),
+            _ => self.pieces.push(Piece::Raw(text.to_string())),
+        }
+    }
+
+    fn unit(&mut self, unit: Unit) {
+        self.pieces.push(Piece::Unit(self.units.len()));
+        self.units.push(unit);
+    }
+
+    fn translate<T: Translate, K: Tokenizer>(
+        self,
+        translator: &mut T,
+        tokenizer: &K,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<(String, LocalizationReport), String> {
+        let texts: Vec<String> = self.units.iter().map(|v| v.text.clone()).collect();
+        let translations = translate_texts(
+            translator,
+            tokenizer,
+            &texts,
+            max_batch_size,
+            options,
+            batch_type,
+        )?;
+        let mut report = LocalizationReport {
+            translated: self.units.len(),
+            ..Default::default()
+        };
+        let outputs: Vec<String> = self
+            .units
+            .iter()
+            .zip(translations)
+            .map(|(unit, translation)| {
+                if !translation.is_valid() {
+                    report.invalid.push(unit.source.clone());
+                }
+                let text = match &unit.html_codes {
+                    Some(codes) => join_inline(&translation.text, codes),
+                    None => translation.text,
+                };
+                restore(&text, &unit.kept)
+            })
+            .collect();
+        let text = self
+            .pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Raw(text) => text.as_str(),
+                Piece::Unit(i) => outputs[*i].as_str(),
+            })
+            .collect();
+        Ok((text, report))
+    }
+}
+
+/// Replaces a part to keep with a self-closing tag.
+fn keep(kept: &mut Vec<(String, String)>, original: &str) -> String {
+    let tag = format!("<x-{}/>", kept.len() + 1);
+    kept.push((tag.clone(), original.to_string()));
+    tag
+}
+
+/// Replaces a hard line break with a tag after a space, so the words around it stay apart; the
+/// space is dropped again with the tag.
+fn keep_break(kept: &mut Vec<(String, String)>, original: &str) -> String {
+    let tag = format!(" <x-{}/>", kept.len() + 1);
+    kept.push((tag.clone(), original.to_string()));
+    kept.push((tag[1..].to_string(), original.to_string()));
+    tag
+}
+
+/// Replaces the delimiters of a span (emphasis, link) with an element.
+fn keep_pair(kept: &mut Vec<(String, String)>, open: &str, close: &str) -> (String, String) {
+    let n = kept.len() + 1;
+    let tags = (format!("<x-{}>", n), format!("</x-{}>", n));
+    kept.push((tags.0.clone(), open.to_string()));
+    kept.push((tags.1.clone(), close.to_string()));
+    tags
+}
+
+fn restore(text: &str, kept: &[(String, String)]) -> String {
+    kept.iter().fold(text.to_string(), |text, (tag, original)| {
+        text.replace(tag, original)
+    })
+}
+
+fn is_translatable(text: &str) -> bool {
+    text.chars().any(char::is_alphabetic)
+}
+
+fn parse_markdown(text: &str, document: &mut Document) {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut i = 0;
+    // YAML front matter.
+    if lines.first().is_some_and(|v| v.trim_end() == "---") {
+        if let Some(end) = (1..lines.len()).find(|&j| matches!(lines[j].trim_end(), "---" | "..."))
+        {
+            lines[..=end].iter().for_each(|v| document.raw(v));
+            i = end + 1;
+        }
+    }
+
+    let mut paragraph: Option<Paragraph> = None;
+    let flush = |paragraph: &mut Option<Paragraph>, document: &mut Document| {
+        if let Some(paragraph) = paragraph.take() {
+            document.raw(&paragraph.prefix);
+            markdown_unit(&paragraph.text, &paragraph.breaks, document);
+            document.raw(&paragraph.ending);
+        }
+    };
+    let mut fence: Option<String> = None;
+    let mut html: Option<String> = None;
+    let (mut after_list, mut in_table) = (false, false);
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        let body = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[body.len()..];
+        let inner = &body[quote_len(body)..];
+        let trimmed = inner.trim_start();
+
+        if let Some(marker) = &fence {
+            document.raw(line);
+            let fence_char = marker.chars().next().unwrap_or('`');
+            if trimmed.starts_with(marker.as_str())
+                && trimmed.trim_end().trim_end_matches(fence_char).is_empty()
+            {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(block) = &mut html {
+            if body.trim().is_empty() {
+                parse_html(block, document);
+                html = None;
+                document.raw(line);
+            } else {
+                block.push_str(line);
+            }
+            continue;
+        }
+        if body.trim().is_empty() {
+            flush(&mut paragraph, document);
+            document.raw(line);
+            in_table = false;
+            continue;
+        }
+        let indent = inner.len() - trimmed.len();
+        if indent == 0 && !prefix_len(body).1 {
+            after_list = false;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush(&mut paragraph, document);
+            let fence_char = trimmed.chars().next().unwrap_or('`');
+            fence = Some(trimmed.chars().take_while(|&c| c == fence_char).collect());
+            document.raw(line);
+            continue;
+        }
+        if indent >= 4 && paragraph.is_none() && !after_list {
+            document.raw(line);
+            continue;
+        }
+        if paragraph.is_none()
+            && trimmed.starts_with('<')
+            && trimmed[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!')
+        {
+            html = Some(line.to_string());
+            continue;
+        }
+        if is_break(trimmed) || (paragraph.is_none() && is_reference(trimmed)) {
+            flush(&mut paragraph, document);
+            document.raw(line);
+            continue;
+        }
+        let next_is_separator = lines
+            .get(i)
+            .is_some_and(|v| is_table_separator(v.trim_end_matches(['\n', '\r'])));
+        if is_table_separator(body) && in_table {
+            document.raw(line);
+            continue;
+        }
+        if body.contains('|') && (in_table || next_is_separator) {
+            flush(&mut paragraph, document);
+            in_table = true;
+            table_row(body, document);
+            document.raw(ending);
+            continue;
+        }
+
+        let (prefix_len, list, heading) = prefix_len(body);
+        let (prefix, content) = body.split_at(prefix_len);
+        if heading {
+            flush(&mut paragraph, document);
+            document.raw(prefix);
+            markdown_unit(content, &[], document);
+            document.raw(ending);
+            continue;
+        }
+        if list {
+            flush(&mut paragraph, document);
+            after_list = true;
+        }
+        match &mut paragraph {
+            Some(paragraph) => paragraph.push(prefix, content, ending),
+            None => {
+                let mut open = Paragraph {
+                    prefix: prefix.to_string(),
+                    ..Default::default()
+                };
+                open.push("", content, ending);
+                paragraph = Some(open);
+            }
+        }
+    }
+    flush(&mut paragraph, document);
+    if let Some(block) = html {
+        parse_html(&block, document);
+    }
+}
+
+/// Lines of the open paragraph, joined with spaces.
+#[derive(Default)]
+struct Paragraph {
+    /// Prefix of the first line (block quote and list markers).
+    prefix: String,
+    /// Text with a `\n` at every hard line break.
+    text: String,
+    /// Each hard line break as written, up to the text of the next line.
+    breaks: Vec<String>,
+    /// Hard line break at the end of the last line, dropped at the end of the paragraph.
+    pending: Option<String>,
+    ending: String,
+}
+
+impl Paragraph {
+    fn push(&mut self, prefix: &str, content: &str, ending: &str) {
+        let core = content.trim();
+        if !self.text.is_empty() {
+            let indent = &content[..content.len() - content.trim_start().len()];
+            match self.pending.take() {
+                Some(hard_break) => {
+                    if hard_break.starts_with('\\') {
+                        self.text.pop();
+                    }
+                    self.text.push('\n');
+                    self.breaks
+                        .push(format!("{}{}{}", hard_break, prefix, indent));
+                }
+                None => self.text.push(' '),
+            }
+        }
+        self.text.push_str(core);
+        // Two or more trailing spaces, or a trailing backslash that is not escaped.
+        let spaces = content.len() - content.trim_end_matches(' ').len();
+        let backslashes = core.len() - core.trim_end_matches('\\').len();
+        self.pending = if spaces >= 2 {
+            Some(format!("{}{}", &content[content.len() - spaces..], ending))
+        } else if spaces == 0 && backslashes % 2 == 1 {
+            Some(format!("\\{}", ending))
+        } else {
+            None
+        };
+        self.ending = ending.to_string();
+    }
+}
+
+/// `breaks` holds the original of each `\n` in `content`, kept as a tag.
+fn markdown_unit(content: &str, breaks: &[String], document: &mut Document) {
+    let mut source = String::new();
+    for (i, line) in content.split('\n').enumerate() {
+        if i > 0 {
+            source.push_str(breaks.get(i - 1).map_or(" ", |v| v.as_str()));
+        }
+        source.push_str(line);
+    }
+    if !is_translatable(content) {
+        document.raw(&source);
+        return;
+    }
+    let mut kept = vec![];
+    let inline = markdown_inline(content, &mut kept);
+    let mut breaks = breaks.iter();
+    let mut text = String::with_capacity(inline.len());
+    for c in inline.chars() {
+        match c {
+            '\n' => match breaks.next() {
+                Some(hard_break) => text.push_str(&keep_break(&mut kept, hard_break)),
+                None => text.push(' '),
+            },
+            _ => text.push(c),
+        }
+    }
+    document.unit(Unit {
+        source,
+        text,
+        kept,
+        html_codes: None,
+    });
+}
+
+/// Length of the block quote markers of a line, with the space following each marker.
+fn quote_len(line: &str) -> usize {
+    let bytes = line.as_bytes();
+    let mut p = 0;
+    loop {
+        let mut q = p;
+        while matches!(bytes.get(q), Some(b' ' | b'\t')) {
+            q += 1;
+        }
+        if bytes.get(q) != Some(&b'>') {
+            return p;
+        }
+        p = q + 1;
+        if bytes.get(p) == Some(&b' ') {
+            p += 1;
+        }
+    }
+}
+
+/// Length of the container prefix of a line (indentation, block quote markers, list marker and
+/// task box, heading marker), and whether it is a list item or a heading.
+fn prefix_len(line: &str) -> (usize, bool, bool) {
+    let bytes = line.as_bytes();
+    let space = |p: usize| matches!(bytes.get(p), Some(b' ' | b'\t'));
+    let skip_spaces = |mut p: usize| {
+        while space(p) {
+            p += 1;
+        }
+        p
+    };
+    let mut p = skip_spaces(quote_len(line));
+    let digits = bytes[p..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let list = if matches!(bytes.get(p), Some(b'-' | b'*' | b'+')) && space(p + 1) {
+        p = skip_spaces(p + 1);
+        true
+    } else if (1..=9).contains(&digits)
+        && matches!(bytes.get(p + digits), Some(b'.' | b')'))
+        && space(p + digits + 1)
+    {
+        p = skip_spaces(p + digits + 1);
+        true
+    } else {
+        false
+    };
+    if list {
+        if let Some(b"[ ] " | b"[x] " | b"[X] ") = bytes.get(p..p + 4) {
+            p = skip_spaces(p + 4);
+        }
+        return (p, true, false);
+    }
+    let hashes = bytes[p..].iter().take_while(|&&c| c == b'#').count();
+    if (1..=6).contains(&hashes) && (p + hashes == bytes.len() || space(p + hashes)) {
+        return (skip_spaces(p + hashes), false, true);
+    }
+    (p, false, false)
+}
+
+/// Thematic break (`***`, `- - -`) or setext heading underline (`===`, `---`).
+fn is_break(trimmed: &str) -> bool {
+    let marks: Vec<char> = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.first().is_some_and(|&first| {
+        marks.iter().all(|&c| c == first)
+            && match first {
+                '-' | '*' | '_' => marks.len() >= 3,
+                '=' => true,
+                _ => false,
+            }
+    })
+}
+
+/// Link reference definition, e.g. `[guide]: https://example.com`.
+fn is_reference(trimmed: &str) -> bool {
+    trimmed.starts_with('[') && trimmed.contains("]:")
+}
+
+/// Delimiter row of a table, e.g. `|---|:--:|`.
+fn is_table_separator(line: &str) -> bool {
+    line.contains('-')
+        && line
+            .trim()
+            .chars()
+            .all(|c| matches!(c, '|' | ':' | '-' | ' ' | '\t'))
+}
+
+/// Translates every cell of a table row.
+fn table_row(line: &str, document: &mut Document) {
+    let mut start = 0;
+    let bytes = line.as_bytes();
+    for end in (0..=line.len())
+        .filter(|&p| p == line.len() || (bytes[p] == b'|' && (p == 0 || bytes[p - 1] != b'\\')))
+    {
+        let cell = &line[start..end];
+        let content = cell.trim();
+        let before = &cell[..cell.len() - cell.trim_start().len()];
+        document.raw(before);
+        markdown_unit(content, &[], document);
+        document.raw(&cell[before.len() + content.len()..]);
+        if end < line.len() {
+            document.raw("|");
+        }
+        start = end + 1;
+    }
+}
+
+/// Converts inline Markdown to markup: code spans, URLs and escapes are kept as self-closing tags,
+/// emphasis and links become elements around their text.
+fn markdown_inline(text: &str, kept: &mut Vec<(String, String)>) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let s = |a: usize, b: usize| chars[a..b].iter().collect::<String>();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let run = chars[i..].iter().take_while(|&&v| v == c).count();
+        if c == '\\' && chars.get(i + 1).is_some_and(|v| v.is_ascii_punctuation()) {
+            out.push_str(&keep(kept, &s(i, i + 2)));
+            i += 2;
+            continue;
+        }
+        if c == '`' {
+            let close = (i + run..chars.len()).find(|&j| {
+                chars[j] == '`'
+                    && chars[j - 1] != '`'
+                    && chars[j..].iter().take_while(|&&v| v == '`').count() == run
+            });
+            match close {
+                Some(j) => {
+                    out.push_str(&keep(kept, &s(i, j + run)));
+                    i = j + run;
+                }
+                None => {
+                    out.push_str(&s(i, i + run));
+                    i += run;
+                }
+            }
+            continue;
+        }
+        if c == '<' {
+            let end = chars[i..].iter().position(|&v| v == '>').map(|v| i + v + 1);
+            let inner = end.map(|end| s(i + 1, end - 1)).unwrap_or_default();
+            match end {
+                // Autolinks are kept, inline HTML goes through as a tag.
+                Some(end) if inner.contains("://") || inner.contains('@') => {
+                    if inner.contains(char::is_whitespace) {
+                        out.push_str(&s(i, end));
+                    } else {
+                        out.push_str(&keep(kept, &s(i, end)));
+                    }
+                    i = end;
+                }
+                Some(end) if inner.starts_with(|v: char| v.is_ascii_alphabetic() || v == '/') => {
+                    out.push_str(&s(i, end));
+                    i = end;
+                }
+                _ => {
+                    out.push('<');
+                    i += 1;
+                }
+            }
+            continue;
+        }
+        let rest = s(i, chars.len());
+        if (rest.starts_with("http://") || rest.starts_with("https://"))
+            && (i == 0 || !chars[i - 1].is_alphanumeric())
+        {
+            let url = rest
+                .split(|v: char| v.is_whitespace() || v == '<')
+                .next()
+                .unwrap_or("");
+            let mut url = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            if !url.contains('(') {
+                url = url.trim_end_matches(')');
+            }
+            out.push_str(&keep(kept, url));
+            i += url.chars().count();
+            continue;
+        }
+        if c == '[' || (c == '!' && chars.get(i + 1) == Some(&'[')) {
+            let start = if c == '!' { i + 2 } else { i + 1 };
+            if let Some((close, end)) = link_end(&chars, start) {
+                let (open, close_tag) = keep_pair(kept, &s(i, start), &s(close, end));
+                out.push_str(&open);
+                out.push_str(&markdown_inline(&s(start, close), kept));
+                out.push_str(&close_tag);
+                i = end;
+                continue;
+            }
+        }
+        if matches!(c, '*' | '_' | '~') {
+            let delimiter = &chars[i..i + run];
+            let opens = chars.get(i + run).is_some_and(|v| !v.is_whitespace())
+                && (c != '_' || i == 0 || !chars[i - 1].is_alphanumeric())
+                && (c != '~' || run == 2);
+            let close = (i + run + 1..chars.len()).filter(|_| opens).find(|&j| {
+                chars[j..].starts_with(delimiter)
+                    && !chars[j - 1].is_whitespace()
+                    && chars[j - 1] != c
+                    && chars.get(j + run) != Some(&c)
+                    && (c != '_' || chars.get(j + run).is_none_or(|v| !v.is_alphanumeric()))
+            });
+            match close {
+                Some(j) => {
+                    let delimiter = s(i, i + run);
+                    let (open, close_tag) = keep_pair(kept, &delimiter, &delimiter);
+                    out.push_str(&open);
+                    out.push_str(&markdown_inline(&s(i + run, j), kept));
+                    out.push_str(&close_tag);
+                    i = j + run;
+                }
+                None => {
+                    out.push_str(&s(i, i + run));
+                    i += run;
+                }
+            }
+            continue;
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
+/// Position of the `]` closing the text of a link starting at `start`, and the end of the link
+/// destination (`(url "title")`) or reference (`[ref]`).
+fn link_end(chars: &[char], start: usize) -> Option<(usize, usize)> {
+    let matching = |from: usize, open: char, close: char| {
+        let mut depth = 0;
+        let mut j = from;
+        while j < chars.len() {
+            match chars[j] {
+                '\\' => j += 1,
+                c if c == open => depth += 1,
+                c if c == close => {
+                    if depth == 0 {
+                        return Some(j);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+            j += 1;
+        }
+        None
+    };
+    let close = matching(start, '[', ']')?;
+    let end = match chars.get(close + 1)? {
+        '(' => matching(close + 2, '(', ')')?,
+        '[' => matching(close + 2, '[', ']')?,
+        _ => return None,
+    };
+    Some((close, end + 1))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HtmlToken {
+    Text,
+    Open(String),
+    Close(String),
+    SelfClosing(String),
+    /// Comments, doctype and processing instructions.
+    Other,
+}
+
+fn html_tokens(text: &str) -> Vec<(&str, HtmlToken)> {
+    let mut res = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let tag = if rest.starts_with("<!--") {
+            Some((
+                rest.find("-->").map_or(rest.len(), |v| v + 3),
+                HtmlToken::Other,
+            ))
+        } else if rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || "/!?".contains(c))
+        {
+            tag_len(rest).map(|len| (len, tag_kind(&rest[..len])))
+        } else {
+            None
+        };
+        let (len, kind) = tag.unwrap_or_else(|| {
+            let len = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| c == '<')
+                .map_or(rest.len(), |(i, _)| i);
+            (len, HtmlToken::Text)
+        });
+        res.push((&rest[..len], kind));
+        rest = &rest[len..];
+    }
+    res
+}
+
+/// Length of the tag at the start of `text`, skipping `>` in quoted attribute values.
+fn tag_len(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn tag_kind(tag: &str) -> HtmlToken {
+    if tag.starts_with("<!") || tag.starts_with("<?") {
+        return HtmlToken::Other;
+    }
+    let closing = tag.starts_with("</");
+    let name: String = tag[if closing { 2 } else { 1 }..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if closing {
+        HtmlToken::Close(name)
+    } else if tag.ends_with("/>") || VOID_ELEMENTS.contains(&name.as_str()) {
+        HtmlToken::SelfClosing(name)
+    } else {
+        HtmlToken::Open(name)
+    }
+}
+
+/// Value of an attribute of a start tag, quoted either way or unquoted; names ignore case.
+fn html_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let inner = tag.strip_prefix('<')?.trim_end_matches('>');
+    let mut rest = inner.trim_start_matches(|c: char| !c.is_whitespace() && c != '/');
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len())
+            .max(1);
+        let attribute = &rest[..len];
+        rest = rest[len..].trim_start();
+        let mut value = "";
+        if let Some(v) = rest.strip_prefix('=') {
+            let v = v.trim_start();
+            let (start, end) = match v.chars().next() {
+                Some(q @ ('"' | '\'')) => (1, v[1..].find(q).map_or(v.len(), |e| e + 1)),
+                _ => (0, v.find(char::is_whitespace).unwrap_or(v.len())),
+            };
+            value = &v[start..end];
+            rest = v.get(end + start..).unwrap_or("");
+        }
+        if attribute.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+    }
+}
+
+fn parse_html(text: &str, document: &mut Document) {
+    let tokens = html_tokens(text);
+    let mut run = String::new();
+    let mut kept = vec![];
+    let mut k = 0;
+    while k < tokens.len() {
+        let (raw, kind) = &tokens[k];
+        k += 1;
+        let name = match kind {
+            HtmlToken::Open(name) | HtmlToken::Close(name) | HtmlToken::SelfClosing(name) => {
+                name.as_str()
+            }
+            _ => "",
+        };
+        let verbatim = VERBATIM_ELEMENTS.contains(&name)
+            || html_attribute(raw, "translate").is_some_and(|v| v.eq_ignore_ascii_case("no"))
+            || html_attribute(raw, "class")
+                .is_some_and(|v| v.split_ascii_whitespace().any(|c| c == "notranslate"));
+        if let (HtmlToken::Open(_), true) = (kind, verbatim || CODE_ELEMENTS.contains(&name)) {
+            // The element up to its end tag.
+            let mut depth = 1;
+            let start = k - 1;
+            while k < tokens.len() && depth > 0 {
+                match &tokens[k].1 {
+                    HtmlToken::Open(n) if n == name => depth += 1,
+                    HtmlToken::Close(n) if n == name => depth -= 1,
+                    _ => {}
+                }
+                k += 1;
+            }
+            let element: String = tokens[start..k].iter().map(|v| v.0).collect();
+            if verbatim {
+                flush_html(&mut run, &mut kept, document);
+                document.raw(&element);
+            } else {
+                run.push_str(&keep(&mut kept, &element));
+            }
+            continue;
+        }
+        match kind {
+            HtmlToken::Text => run.push_str(raw),
+            _ if INLINE_ELEMENTS.contains(&name) => run.push_str(raw),
+            _ => {
+                flush_html(&mut run, &mut kept, document);
+                document.raw(raw);
+            }
+        }
+    }
+    flush_html(&mut run, &mut kept, document);
+}
+
+/// Adds a run of text and inline elements as a unit, with its whitespace collapsed.
+fn flush_html(run: &mut String, kept: &mut Vec<(String, String)>, document: &mut Document) {
+    let text = std::mem::take(run);
+    let kept = std::mem::take(kept);
+    let (plain, _) = split_inline(&text);
+    if !markup::parse(&plain)
+        .words
+        .iter()
+        .any(|v| is_translatable(v))
+    {
+        document.raw(&restore(&text, &kept));
+        return;
+    }
+    let core = text.trim();
+    let before = &text[..text.len() - text.trim_start().len()];
+    document.raw(before);
+
+    let mut collapsed = String::with_capacity(core.len());
+    let (mut in_tag, mut space) = (false, false);
+    for c in core.chars() {
+        in_tag |= c == '<';
+        if !in_tag && c.is_whitespace() {
+            if !space {
+                collapsed.push(' ');
+            }
+            space = true;
+            continue;
+        }
+        space = false;
+        collapsed.push(c);
+        in_tag &= c != '>';
+    }
+    let (unescaped, codes) = split_inline(&collapsed);
+    document.unit(Unit {
+        source: restore(core, &kept),
+        text: unescaped,
+        kept,
+        html_codes: Some(codes),
+    });
+    document.raw(&text[before.len() + core.len()..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn translates_markdown() {
+        let text = "---\ntitle: Guide\n---\n# Getting started\n\n\
+                    Install with `cargo add x` and read\n\
+                    the [guide](https://example.com/a_b \"Guide\") or **this** note.\n\n\
+                    - first item\n- see https://example.com/x.\n\n\
+                    ```rust\nlet x = \"kept\";\n```\n\n    indented code\n\n\
+                    | Name | Value |\n|------|-------|\n| size | `10` |\n\n\
+                    <div class=\"note\">Hello <b>world</b></div>\n\n\
+                    > roses are red  \n> violets blue\\\nsugar\n";
+        let mut translator = MockTranslator::upper();
+        let (output, report) = translate_markdown(
+            &mut translator,
+            &WhitespaceTokenizer,
+            text,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(translator.batches.len(), 1);
+        assert!(report.invalid.is_empty(), "{:?}", report);
+        assert_eq!(
+            output,
+            "---\ntitle: Guide\n---\n# GETTING STARTED\n\n\
+             INSTALL WITH `cargo add x` AND READ THE [GUIDE](https://example.com/a_b \"Guide\") OR **THIS** NOTE.\n\n\
+             - FIRST ITEM\n- SEE https://example.com/x.\n\n\
+             ```rust\nlet x = \"kept\";\n```\n\n    indented code\n\n\
+             | NAME | VALUE |\n|------|-------|\n| SIZE | `10` |\n\n\
+             <div class=\"note\">HELLO <b>WORLD</b></div>\n\n\
+             > ROSES ARE RED  \n> VIOLETS BLUE\\\nSUGAR\n"
+        );
+        assert_eq!(report.translated, 9);
+    }
+
+    #[test]
+    fn translates_html() {
+        let text = "<!DOCTYPE html>\n<html><head><title>My page</title>\
+                    <script>let s = \"no\";</script></head>\n<body>\n\
+                    <p title=\"keep me\">Run   <code>make all</code> &amp; <a href=\"/x\">wait</a> now.</p>\n\
+                    <pre>kept\n  text</pre><p translate=\"no\">Brand</p><ul><li>one</li></ul>\n\
+                    <p translate='no'>Name</p><p class=\"x notranslate\">Term</p>\n\
+                    <p><a href=\"/notranslate-guide\">guide</a></p>\n\
+                    <p>été chaud</p>\n\
+                    </body></html>";
+        let mut translator = MockTranslator::upper();
+        let (output, report) = translate_html(
+            &mut translator,
+            &WhitespaceTokenizer,
+            text,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(report.translated, 5);
+        assert_eq!(
+            output,
+            "<!DOCTYPE html>\n<html><head><title>MY PAGE</title>\
+             <script>let s = \"no\";</script></head>\n<body>\n\
+             <p title=\"keep me\">RUN <code>make all</code> &amp; <a href=\"/x\">WAIT</a> NOW.</p>\n\
+             <pre>kept\n  text</pre><p translate=\"no\">Brand</p><ul><li>ONE</li></ul>\n\
+             <p translate='no'>Name</p><p class=\"x notranslate\">Term</p>\n\
+             <p><a href=\"/notranslate-guide\">GUIDE</a></p>\n\
+             <p>ÉTÉ CHAUD</p>\n\
+             </body></html>"
+        );
+    }
+}
@@ -0,0 +1,701 @@
+//! Corpus level evaluation against reference translations: BLEU, chrF++ and TER following
+//! sacreBLEU's defaults, and paired bootstrap resampling to compare two systems.
+//!
+//! Scores are in `[0, 100]`; lower is better for TER.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::metrics::ngram_matches;
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Bleu,
+    ChrF,
+    Ter,
+}
+
+impl Metric {
+    pub fn higher_is_better(self) -> bool {
+        self != Metric::Ter
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Metric::Bleu => "BLEU",
+            Metric::ChrF => "chrF2++",
+            Metric::Ter => "TER",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Scores {
+    pub bleu: f64,
+    /// chrF++: character 6-grams and word bigrams.
+    pub chrf: f64,
+    pub ter: f64,
+}
+
+impl Scores {
+    pub fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Bleu => self.bleu,
+            Metric::ChrF => self.chrf,
+            Metric::Ter => self.ter,
+        }
+    }
+}
+
+/// Result of [`paired_bootstrap`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bootstrap {
+    pub metric: Metric,
+    pub baseline: f64,
+    pub system: f64,
+    /// 95% confidence interval of the system score.
+    pub interval: (f64, f64),
+    /// Share of the samples in which the system is not better than the baseline.
+    pub p_value: f64,
+}
+
+/// Translates one sentence per line.
+pub fn translate_lines<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    lines: &[String],
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<Vec<String>, String> {
+    let input = lines.iter().map(|v| tokenizer.encode(v)).collect();
+    Ok(translator
+        .translate_batch(input, max_batch_size, options, batch_type)?
+        .iter()
+        .map(|v| tokenizer.decode(v))
+        .collect())
+}
+
+/// Corpus BLEU, chrF++ and TER of the hypotheses against one reference each.
+pub fn evaluate(hypotheses: &[String], references: &[String]) -> Result<Scores, String> {
+    check_lengths(hypotheses, references)?;
+    Ok(Scores {
+        bleu: corpus_score::<BleuStats>(hypotheses, references),
+        chrf: corpus_score::<ChrfStats>(hypotheses, references),
+        ter: corpus_score::<TerStats>(hypotheses, references),
+    })
+}
+
+pub fn corpus_bleu(hypotheses: &[String], references: &[String]) -> f64 {
+    corpus_score::<BleuStats>(hypotheses, references)
+}
+
+pub fn corpus_chrf(hypotheses: &[String], references: &[String]) -> f64 {
+    corpus_score::<ChrfStats>(hypotheses, references)
+}
+
+pub fn corpus_ter(hypotheses: &[String], references: &[String]) -> f64 {
+    corpus_score::<TerStats>(hypotheses, references)
+}
+
+/// Compares two systems on `samples` resampled test sets (sacreBLEU uses 1000).
+pub fn paired_bootstrap(
+    metric: Metric,
+    references: &[String],
+    baseline: &[String],
+    system: &[String],
+    samples: usize,
+    seed: u64,
+) -> Result<Bootstrap, String> {
+    check_lengths(baseline, references)?;
+    check_lengths(system, references)?;
+    match metric {
+        Metric::Bleu => bootstrap::<BleuStats>(metric, references, baseline, system, samples, seed),
+        Metric::ChrF => bootstrap::<ChrfStats>(metric, references, baseline, system, samples, seed),
+        Metric::Ter => bootstrap::<TerStats>(metric, references, baseline, system, samples, seed),
+    }
+}
+
+/// sacreBLEU's default `13a` tokenization (mteval-v13a).
+pub fn tokenize_13a(text: &str) -> Vec<String> {
+    let text = text
+        .replace("<skipped>", "")
+        .replace("-\n", "")
+        .replace('\n', " ")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">");
+    static RULES: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        [
+            (r"([\{-~\[-` -&\(-\+:-@/])", " $1 "),
+            (r"([^0-9])([\.,])", "$1 $2 "),
+            (r"([\.,])([^0-9])", " $1 $2"),
+            (r"([0-9])(-)", "$1 $2 "),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+        .collect()
+    });
+    let mut text = format!(" {} ", text);
+    for (re, replacement) in rules {
+        text = re.replace_all(&text, *replacement).into_owned();
+    }
+    text.split_whitespace().map(|v| v.to_string()).collect()
+}
+
+fn check_lengths(hypotheses: &[String], references: &[String]) -> Result<(), String> {
+    if hypotheses.len() != references.len() {
+        return Err(format!(
+            "{} hypotheses but {} references",
+            hypotheses.len(),
+            references.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Sufficient statistics of a metric, summed over the sentences of a corpus.
+trait Stats: Clone + Default {
+    fn sentence(hypothesis: &str, reference: &str) -> Self;
+    fn add(&mut self, other: &Self);
+    fn score(&self) -> f64;
+}
+
+fn corpus_score<S: Stats>(hypotheses: &[String], references: &[String]) -> f64 {
+    let mut total = S::default();
+    for (h, r) in hypotheses.iter().zip(references) {
+        total.add(&S::sentence(h, r));
+    }
+    total.score()
+}
+
+fn bootstrap<S: Stats>(
+    metric: Metric,
+    references: &[String],
+    baseline: &[String],
+    system: &[String],
+    samples: usize,
+    seed: u64,
+) -> Result<Bootstrap, String> {
+    if references.is_empty() || samples == 0 {
+        return Err("bootstrap needs sentences and samples".to_string());
+    }
+    let stats = |hypotheses: &[String]| -> Vec<S> {
+        hypotheses
+            .iter()
+            .zip(references)
+            .map(|(h, r)| S::sentence(h, r))
+            .collect()
+    };
+    let (baseline, system) = (stats(baseline), stats(system));
+    let total = |stats: &[S], indices: &mut dyn Iterator<Item = usize>| {
+        let mut res = S::default();
+        for i in indices {
+            res.add(&stats[i]);
+        }
+        res.score()
+    };
+
+    let mut rng = seed;
+    let mut scores = vec![];
+    let mut not_better = 0;
+    for _ in 0..samples {
+        let indices: Vec<usize> = (0..references.len())
+            .map(|_| (split_mix(&mut rng) % references.len() as u64) as usize)
+            .collect();
+        let b = total(&baseline, &mut indices.iter().copied());
+        let s = total(&system, &mut indices.iter().copied());
+        let better = if metric.higher_is_better() {
+            s > b
+        } else {
+            s < b
+        };
+        not_better += usize::from(!better);
+        scores.push(s);
+    }
+    scores.sort_by(f64::total_cmp);
+    let at = |q: f64| scores[((samples - 1) as f64 * q).round() as usize];
+    Ok(Bootstrap {
+        metric,
+        baseline: total(&baseline, &mut (0..references.len())),
+        system: total(&system, &mut (0..references.len())),
+        interval: (at(0.025), at(0.975)),
+        p_value: not_better as f64 / samples as f64,
+    })
+}
+
+/// SplitMix64, enough for resampling without a dependency on a random number crate.
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[derive(Clone, Debug, Default)]
+struct BleuStats {
+    matches: [usize; 4],
+    totals: [usize; 4],
+    hypothesis_length: usize,
+    reference_length: usize,
+}
+
+impl Stats for BleuStats {
+    fn sentence(hypothesis: &str, reference: &str) -> Self {
+        let hypothesis = tokenize_13a(hypothesis);
+        let reference = tokenize_13a(reference);
+        let mut res = Self {
+            hypothesis_length: hypothesis.len(),
+            reference_length: reference.len(),
+            ..Default::default()
+        };
+        for n in 0..4 {
+            let (matches, total, _) = ngram_matches(&hypothesis, &reference, n + 1);
+            res.matches[n] = matches;
+            res.totals[n] = total;
+        }
+        res
+    }
+
+    fn add(&mut self, other: &Self) {
+        for n in 0..4 {
+            self.matches[n] += other.matches[n];
+            self.totals[n] += other.totals[n];
+        }
+        self.hypothesis_length += other.hypothesis_length;
+        self.reference_length += other.reference_length;
+    }
+
+    /// Geometric mean of the n-gram precisions with the `exp` smoothing of sacreBLEU.
+    fn score(&self) -> f64 {
+        if self.hypothesis_length == 0 {
+            return 0.0;
+        }
+        let mut smoothing = 1.0;
+        let mut log_precision = 0.0;
+        for n in 0..4 {
+            if self.totals[n] == 0 {
+                return 0.0;
+            }
+            let precision = if self.matches[n] == 0 {
+                smoothing *= 2.0;
+                1.0 / (smoothing * self.totals[n] as f64)
+            } else {
+                self.matches[n] as f64 / self.totals[n] as f64
+            };
+            log_precision += precision.ln() / 4.0;
+        }
+        let (h, r) = (self.hypothesis_length as f64, self.reference_length as f64);
+        let brevity_penalty = if h < r { (1.0 - r / h).exp() } else { 1.0 };
+        100.0 * brevity_penalty * log_precision.exp()
+    }
+}
+
+const CHAR_ORDER: usize = 6;
+const WORD_ORDER: usize = 2;
+const CHRF_BETA: f64 = 2.0;
+
+/// `(hypothesis n-grams, reference n-grams, matches)` of each character order, then each word
+/// order.
+#[derive(Clone, Debug, Default)]
+struct ChrfStats {
+    orders: Vec<[usize; 3]>,
+}
+
+impl Stats for ChrfStats {
+    fn sentence(hypothesis: &str, reference: &str) -> Self {
+        let chars = |v: &str| v.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+        let (h, r) = (chars(hypothesis), chars(reference));
+        let mut orders: Vec<[usize; 3]> = (1..=CHAR_ORDER)
+            .map(|n| {
+                let (matches, h, r) = ngram_matches(&h, &r, n);
+                [h, r, matches]
+            })
+            .collect();
+        let (h, r) = (chrf_words(hypothesis), chrf_words(reference));
+        orders.extend((1..=WORD_ORDER).map(|n| {
+            let (matches, h, r) = ngram_matches(&h, &r, n);
+            [h, r, matches]
+        }));
+        Self { orders }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.orders
+            .resize(other.orders.len().max(self.orders.len()), [0; 3]);
+        for (a, b) in self.orders.iter_mut().zip(&other.orders) {
+            for i in 0..3 {
+                a[i] += b[i];
+            }
+        }
+    }
+
+    /// sacreBLEU's default: precision and recall averaged over the orders found in both sides,
+    /// then one F-score (beta 2).
+    fn score(&self) -> f64 {
+        let effective = self
+            .orders
+            .iter()
+            .filter(|[h, r, _]| *h > 0 && *r > 0)
+            .count();
+        if effective == 0 {
+            return 0.0;
+        }
+        // sacreBLEU counts an order missing from one side with a tiny epsilon.
+        let ratio = |m: usize, n: usize| if n > 0 { m as f64 / n as f64 } else { 1e-16 };
+        let average = |f: &dyn Fn(&[usize; 3]) -> f64| {
+            self.orders.iter().map(f).sum::<f64>() / effective as f64
+        };
+        let precision = average(&|[h, _, m]| ratio(*m, *h));
+        let recall = average(&|[_, r, m]| ratio(*m, *r));
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        let factor = CHRF_BETA * CHRF_BETA;
+        100.0 * (1.0 + factor) * precision * recall / (factor * precision + recall)
+    }
+}
+
+/// Words for the word n-grams of chrF++: punctuation at the end or start of a word is split off.
+fn chrf_words(text: &str) -> Vec<String> {
+    let mut res = vec![];
+    for word in text.split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+        match (chars.first(), chars.last()) {
+            (_, Some(last)) if chars.len() > 1 && last.is_ascii_punctuation() => {
+                res.push(chars[..chars.len() - 1].iter().collect());
+                res.push(last.to_string());
+            }
+            (Some(first), _) if chars.len() > 1 && first.is_ascii_punctuation() => {
+                res.push(first.to_string());
+                res.push(chars[1..].iter().collect());
+            }
+            _ => res.push(word.to_string()),
+        }
+    }
+    res
+}
+
+/// Longest phrase moved by one shift.
+const MAX_SHIFT_SIZE: usize = 10;
+/// Farthest apart a phrase may start in the hypothesis and in the reference.
+const MAX_SHIFT_DISTANCE: usize = 50;
+/// Shifts tried per sentence before the search stops.
+const MAX_SHIFT_CANDIDATES: usize = 1000;
+/// Cells computed on each side of the diagonal by the edit distance.
+const BEAM_WIDTH: usize = 25;
+
+#[derive(Clone, Debug, Default)]
+struct TerStats {
+    edits: usize,
+    reference_length: usize,
+}
+
+impl Stats for TerStats {
+    /// Lower-cased words; tercom's shifts are applied while the best one lowers the edit
+    /// distance, each shift counting as one edit.
+    fn sentence(hypothesis: &str, reference: &str) -> Self {
+        let words = |v: &str| {
+            v.split_whitespace()
+                .map(|w| w.to_lowercase())
+                .collect::<Vec<_>>()
+        };
+        let (mut hypothesis, reference) = (words(hypothesis), words(reference));
+        let mut shifts = 0;
+        let mut checked = 0;
+        while let Some(shifted) = best_shift(&hypothesis, &reference, &mut checked) {
+            if checked >= MAX_SHIFT_CANDIDATES {
+                break;
+            }
+            hypothesis = shifted;
+            shifts += 1;
+        }
+        Self {
+            edits: ter_edit_distance(&hypothesis, &reference).0 + shifts,
+            reference_length: reference.len(),
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.edits += other.edits;
+        self.reference_length += other.reference_length;
+    }
+
+    fn score(&self) -> f64 {
+        match (self.edits, self.reference_length) {
+            (0, _) => 0.0,
+            (_, 0) => 100.0,
+            (e, r) => 100.0 * e as f64 / r as f64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Substitute,
+    /// A hypothesis word is dropped.
+    Delete,
+    /// A reference word is added.
+    Insert,
+}
+
+/// Word edit distance from the hypothesis to the reference and its edits, computed in a band
+/// around the diagonal with tercom's preference between equal paths.
+fn ter_edit_distance(hypothesis: &[String], reference: &[String]) -> (usize, Vec<Edit>) {
+    let (h, r) = (hypothesis.len(), reference.len());
+    let ratio = if h == 0 { 1.0 } else { r as f64 / h as f64 };
+    // Very unequal lengths widen the band so that consecutive rows still overlap.
+    let beam = if (BEAM_WIDTH as f64) < ratio / 2.0 {
+        (ratio / 2.0 + BEAM_WIDTH as f64).ceil() as usize
+    } else {
+        BEAM_WIDTH
+    };
+    let mut table = vec![(0..=r).map(|j| (j, Edit::Insert)).collect::<Vec<_>>()];
+    for i in 1..=h {
+        let diagonal = (i as f64 * ratio).floor() as usize;
+        let end = if i == h {
+            r + 1
+        } else {
+            (r + 1).min(diagonal + beam)
+        };
+        let previous = &table[i - 1];
+        let mut row = vec![(usize::MAX, Edit::Keep); r + 1];
+        for j in diagonal.saturating_sub(beam)..end {
+            if j == 0 {
+                row[0] = (previous[0].0.saturating_add(1), Edit::Delete);
+                continue;
+            }
+            let replace = if hypothesis[i - 1] == reference[j - 1] {
+                (previous[j - 1].0, Edit::Keep)
+            } else {
+                (previous[j - 1].0.saturating_add(1), Edit::Substitute)
+            };
+            let above = (previous[j].0.saturating_add(1), Edit::Delete);
+            let left = (row[j - 1].0.saturating_add(1), Edit::Insert);
+            for (cost, edit) in [replace, above, left] {
+                if cost < row[j].0 {
+                    row[j] = (cost, edit);
+                }
+            }
+        }
+        table.push(row);
+    }
+
+    let mut edits = vec![];
+    let (mut i, mut j) = (h, r);
+    while i > 0 || j > 0 {
+        let edit = table[i][j].1;
+        edits.push(edit);
+        match edit {
+            Edit::Keep | Edit::Substitute => (i, j) = (i - 1, j - 1),
+            Edit::Delete => i -= 1,
+            Edit::Insert => j -= 1,
+        }
+    }
+    edits.reverse();
+    (table[h][r].0, edits)
+}
+
+/// The hypothesis after tercom's best shift, if one lowers the edit distance, counting the shifts
+/// tried in `checked`.
+///
+/// Only phrases found in the reference that are misaligned on both sides are moved, to the
+/// positions aligned with the reference phrase. Ties go to longer phrases, then earlier ones.
+fn best_shift(
+    hypothesis: &[String],
+    reference: &[String],
+    checked: &mut usize,
+) -> Option<Vec<String>> {
+    let (distance, edits) = ter_edit_distance(hypothesis, reference);
+    // Hypothesis position aligned with each reference word, -1 before the first word.
+    let mut aligned = vec![];
+    let mut hypothesis_errors = vec![];
+    let mut reference_errors = vec![];
+    let mut position = -1isize;
+    for edit in edits {
+        match edit {
+            Edit::Keep | Edit::Substitute => {
+                position += 1;
+                aligned.push(position);
+                hypothesis_errors.push(edit == Edit::Substitute);
+                reference_errors.push(edit == Edit::Substitute);
+            }
+            Edit::Delete => {
+                position += 1;
+                hypothesis_errors.push(true);
+            }
+            Edit::Insert => {
+                aligned.push(position);
+                reference_errors.push(true);
+            }
+        }
+    }
+
+    let mut best = None;
+    let mut best_rank = (0, 0, 0, 0);
+    for (start_h, start_r, length) in shift_pairs(hypothesis, reference) {
+        if !hypothesis_errors[start_h..start_h + length].contains(&true)
+            || !reference_errors[start_r..start_r + length].contains(&true)
+            || (start_h as isize..(start_h + length) as isize).contains(&aligned[start_r])
+        {
+            continue;
+        }
+        let mut previous = None;
+        for offset in -1..length as isize {
+            let target = match start_r as isize + offset {
+                -1 => 0,
+                j if (j as usize) < aligned.len() => (aligned[j as usize] + 1) as usize,
+                _ => break,
+            };
+            if previous == Some(target) {
+                continue;
+            }
+            previous = Some(target);
+            let shifted = shift_words(hypothesis, start_h, length, target);
+            let gain = distance as isize - ter_edit_distance(&shifted, reference).0 as isize;
+            let rank = (gain, length, -(start_h as isize), -(target as isize));
+            *checked += 1;
+            if gain > 0 && rank > best_rank {
+                best = Some(shifted);
+                best_rank = rank;
+            }
+        }
+        if *checked >= MAX_SHIFT_CANDIDATES {
+            break;
+        }
+    }
+    best
+}
+
+/// `(hypothesis start, reference start, length)` of the phrases found in both.
+fn shift_pairs(hypothesis: &[String], reference: &[String]) -> Vec<(usize, usize, usize)> {
+    let mut res = vec![];
+    for start_h in 0..hypothesis.len() {
+        for start_r in 0..reference.len() {
+            if start_h.abs_diff(start_r) > MAX_SHIFT_DISTANCE {
+                continue;
+            }
+            let mut length = 0;
+            while length < MAX_SHIFT_SIZE
+                && start_h + length < hypothesis.len()
+                && start_r + length < reference.len()
+                && hypothesis[start_h + length] == reference[start_r + length]
+            {
+                length += 1;
+                res.push((start_h, start_r, length));
+            }
+        }
+    }
+    res
+}
+
+/// Moves `words[start..start + length]` before `words[target]`, as tercom does.
+fn shift_words(words: &[String], start: usize, length: usize, target: usize) -> Vec<String> {
+    let phrase = &words[start..start + length];
+    if target < start {
+        [
+            &words[..target],
+            phrase,
+            &words[target..start],
+            &words[start + length..],
+        ]
+        .concat()
+    } else if target > start + length {
+        [
+            &words[..start],
+            &words[start + length..target],
+            phrase,
+            &words[target..],
+        ]
+        .concat()
+    } else {
+        let split = (length + target).min(words.len());
+        [
+            &words[..start],
+            &words[start + length..split],
+            phrase,
+            &words[split..],
+        ]
+        .concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(v: &[&str]) -> Vec<String> {
+        v.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn corpus_metrics() {
+        assert_eq!(
+            tokenize_13a("Hello, world! It costs $3.50."),
+            lines(&["Hello", ",", "world", "!", "It", "costs", "$", "3.50", "."])
+        );
+        let bleu = corpus_bleu(
+            &lines(&["the cat sat on the mat"]),
+            &lines(&["the cat sat on a mat"]),
+        );
+        assert!((bleu - 100.0 * (1.0f64 / 12.0).powf(0.25)).abs() < 1e-9);
+
+        let references = lines(&["c d a b", "the dog"]);
+        let hypotheses = lines(&["a b c d", "The cat"]);
+        let ter = corpus_ter(&hypotheses, &references);
+        assert!((ter - 100.0 * 2.0 / 6.0).abs() < 1e-9);
+        // sacreBLEU's TER test case where a single shift fixes the hypothesis.
+        let ter = corpus_ter(&lines(&["d e f g h a b c"]), &lines(&["a b c d e f g h"]));
+        assert_eq!(ter, 100.0 / 8.0);
+
+        let scores = evaluate(&references, &references).unwrap();
+        assert_eq!((scores.bleu, scores.chrf, scores.ter), (100.0, 100.0, 0.0));
+        assert!(corpus_chrf(&lines(&["abc"]), &lines(&["xyz"])) < 1e-9);
+        // sacreBLEU: `CHRF(word_order=2)` and `TER()` `.corpus_score(hypotheses, [references])`.
+        let references = lines(&[
+            "The dog bit the man.",
+            "It was not unexpected.",
+            "The man bit him first.",
+        ]);
+        let hypotheses = lines(&[
+            "The dog bit the man.",
+            "It wasn't surprising.",
+            "The man had just bitten him.",
+        ]);
+        let chrf = corpus_chrf(&hypotheses, &references);
+        assert!((chrf - 51.730_969_850_374_6).abs() < 1e-9);
+        assert_eq!(corpus_ter(&hypotheses, &references), 50.0);
+        assert!(evaluate(&hypotheses, &references[..1]).is_err());
+    }
+
+    #[test]
+    fn bootstrap_prefers_better_system() {
+        let references: Vec<String> = (0..30)
+            .map(|i| format!("sentence number {} is here", i))
+            .collect();
+        let baseline: Vec<String> = (0..30).map(|i| format!("number {} here", i)).collect();
+        for metric in [Metric::Bleu, Metric::ChrF, Metric::Ter] {
+            let result =
+                paired_bootstrap(metric, &references, &baseline, &references, 200, 7).unwrap();
+            let again =
+                paired_bootstrap(metric, &references, &baseline, &references, 200, 7).unwrap();
+            assert_eq!(result, again);
+            assert!(result.p_value < 0.05);
+            assert_eq!(
+                result.system,
+                if metric == Metric::Ter { 0.0 } else { 100.0 }
+            );
+            assert!(result.interval.0 <= result.system && result.system <= result.interval.1);
+        }
+    }
+}
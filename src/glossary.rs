@@ -0,0 +1,450 @@
+//! Terminology enforcement.
+//!
+//! A [`Glossary`] is loaded from CSV or TBX, matched against the words of the tokenized input and
+//! checked against (or forced into) the translation using the attention alignment.
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::alignment::{align_words, merge_words, word_indices, AlignmentMethod};
+use crate::tokenizer::Tokenizer;
+use crate::xml;
+use crate::{BatchType, Translate, TranslationOptions};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Term {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Glossary {
+    pub terms: Vec<Term>,
+    pub case_sensitive: bool,
+}
+
+/// Occurrence of a term in the source words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermMatch {
+    /// Index of the term in the glossary.
+    pub term: usize,
+    pub words: Range<usize>,
+}
+
+/// How the glossary is enforced on the translation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Enforcement {
+    /// Only report which terms are present in the translation.
+    #[default]
+    Check,
+    /// Replace the target words aligned with a matched source term by the target term.
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermStatus {
+    /// The target term was already in the translation.
+    Present,
+    /// The target term was put in the translation by [`Enforcement::Replace`].
+    Replaced,
+    /// The target term is missing from the translation.
+    Violated,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermReport {
+    pub term: Term,
+    /// Source words matched by the term.
+    pub words: Range<usize>,
+    pub status: TermStatus,
+}
+
+/// Translation of one example with the glossary report.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GlossaryTranslation {
+    pub tokens: Vec<String>,
+    pub terms: Vec<TermReport>,
+}
+
+impl GlossaryTranslation {
+    pub fn applied(&self) -> impl Iterator<Item = &TermReport> {
+        self.terms
+            .iter()
+            .filter(|v| v.status != TermStatus::Violated)
+    }
+
+    pub fn violated(&self) -> impl Iterator<Item = &TermReport> {
+        self.terms
+            .iter()
+            .filter(|v| v.status == TermStatus::Violated)
+    }
+}
+
+impl Glossary {
+    pub fn new(terms: Vec<Term>) -> Self {
+        Self {
+            terms,
+            case_sensitive: false,
+        }
+    }
+
+    /// Loads `source,target` rows. Tab separated files are detected from the first row, lines
+    /// starting with `#` are skipped and so is a `source,target` header.
+    pub fn from_csv_str(text: &str) -> Result<Self, String> {
+        let delimiter = match text.lines().next() {
+            Some(line) if line.contains('\t') => '\t',
+            _ => ',',
+        };
+        let mut terms = vec![];
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = split_csv_line(line, delimiter);
+            if fields.len() < 2 {
+                return Err(format!("line {}: expected source and target term", i + 1));
+            }
+            if i == 0
+                && fields[0].eq_ignore_ascii_case("source")
+                && fields[1].eq_ignore_ascii_case("target")
+            {
+                continue;
+            }
+            terms.push(Term {
+                source: fields[0].trim().to_string(),
+                target: fields[1].trim().to_string(),
+            });
+        }
+        Ok(Self::new(terms))
+    }
+
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, String> {
+        Self::from_csv_str(&fs::read_to_string(path).map_err(|e| e.to_string())?)
+    }
+
+    /// Loads the entries of a TBX document having a term in both languages (`xml:lang`, compared
+    /// by primary subtag so `en` matches `en-US`).
+    pub fn from_tbx_str(text: &str, source_lang: &str, target_lang: &str) -> Result<Self, String> {
+        let mut entries = xml::elements(text, "termEntry");
+        entries.extend(xml::elements(text, "conceptEntry"));
+        if entries.is_empty() && !text.contains("<tbx") && !text.contains("<martif") {
+            return Err("not a TBX document".to_string());
+        }
+        let term_in = |entry: &xml::Element, lang: &str| {
+            entry
+                .children("langSet")
+                .into_iter()
+                .chain(entry.children("langSec"))
+                .find(|set| {
+                    set.attribute("xml:lang")
+                        .is_some_and(|v| xml::same_language(&v, lang))
+                })
+                .and_then(|set| set.child("term"))
+                .map(|term| term.text().trim().to_string())
+        };
+        let terms = entries
+            .iter()
+            .filter_map(|entry| {
+                Some(Term {
+                    source: term_in(entry, source_lang)?,
+                    target: term_in(entry, target_lang)?,
+                })
+            })
+            .collect();
+        Ok(Self::new(terms))
+    }
+
+    pub fn from_tbx(
+        path: impl AsRef<Path>,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::from_tbx_str(&text, source_lang, target_lang)
+    }
+
+    /// Non overlapping term occurrences in a tokenized sentence, longest terms first.
+    pub fn find(&self, tokens: &[String]) -> Vec<TermMatch> {
+        let words: Vec<String> = merge_words(tokens)
+            .iter()
+            .map(|v| self.normalize(v))
+            .collect();
+        let mut terms: Vec<(usize, Vec<String>)> = self
+            .terms
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let words = t.source.split_whitespace().map(|v| self.normalize(v));
+                (i, words.collect())
+            })
+            .filter(|(_, words): &(usize, Vec<String>)| !words.is_empty())
+            .collect();
+        terms.sort_by_key(|(_, words)| std::cmp::Reverse(words.len()));
+
+        let mut taken = vec![false; words.len()];
+        let mut res = vec![];
+        for (term, term_words) in terms {
+            let n = term_words.len();
+            for start in 0..words.len().saturating_sub(n - 1) {
+                if words[start..start + n] == term_words[..]
+                    && !taken[start..start + n].iter().any(|v| *v)
+                {
+                    taken[start..start + n].iter_mut().for_each(|v| *v = true);
+                    res.push(TermMatch {
+                        term,
+                        words: start..start + n,
+                    });
+                }
+            }
+        }
+        res.sort_by_key(|m| m.words.start);
+        res
+    }
+
+    /// Lower-cases (unless case sensitive) and strips the punctuation around a word.
+    fn normalize(&self, word: &str) -> String {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if self.case_sensitive {
+            word.to_string()
+        } else {
+            word.to_lowercase()
+        }
+    }
+
+    /// Whether the words of the term appear in a row in the text.
+    pub(crate) fn contains(&self, text: &str, term: &str) -> bool {
+        let words = |v: &str| {
+            v.split_whitespace()
+                .map(|w| self.normalize(w))
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<_>>()
+        };
+        let term = words(term);
+        !term.is_empty() && words(text).windows(term.len()).any(|w| w == term)
+    }
+}
+
+fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted || field.is_empty() => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Translates a batch and applies the glossary to every example.
+///
+/// [`Enforcement::Replace`] needs the attention, so `return_attention` is forced on in that mode.
+/// The tokenizer turns the target terms into tokens and the translation into text for the checks.
+#[allow(clippy::too_many_arguments)]
+pub fn translate_with_glossary<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    glossary: &Glossary,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+    enforcement: Enforcement,
+) -> Result<Vec<GlossaryTranslation>, String> {
+    let mut options = options.unwrap_or_default();
+    options.return_attention |= enforcement == Enforcement::Replace;
+    let matches: Vec<Vec<TermMatch>> = input.iter().map(|v| glossary.find(v)).collect();
+    let results = translator.translate_batch_results(
+        input.clone(),
+        max_batch_size,
+        Some(options),
+        batch_type,
+    )?;
+    if results.len() != input.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+
+    let mut res = vec![];
+    for ((source, result), matches) in input.iter().zip(results).zip(matches) {
+        let mut tokens = result.output().to_vec();
+        let alignment = result
+            .attention
+            .first()
+            .filter(|_| enforcement == Enforcement::Replace)
+            .map(|attention| align_words(source, &tokens, attention, AlignmentMethod::Argmax));
+        let text = tokenizer.decode(&tokens);
+
+        let mut terms = vec![];
+        // Replacements keyed by target word range, applied right to left afterwards.
+        let mut replacements: Vec<(Range<usize>, usize)> = vec![];
+        for m in matches {
+            let term = &glossary.terms[m.term];
+            let mut status = if glossary.contains(&text, &term.target) {
+                TermStatus::Present
+            } else {
+                TermStatus::Violated
+            };
+            if let (TermStatus::Violated, Some(alignment)) = (status, &alignment) {
+                let targets: Vec<usize> = m
+                    .words
+                    .clone()
+                    .flat_map(|w| alignment.target_for(w))
+                    .collect();
+                if let (Some(&start), Some(&end)) = (targets.iter().min(), targets.iter().max()) {
+                    let range = start..end + 1;
+                    if !replacements
+                        .iter()
+                        .any(|(r, _)| r.start < range.end && range.start < r.end)
+                    {
+                        replacements.push((range, terms.len()));
+                        status = TermStatus::Replaced;
+                    }
+                }
+            }
+            terms.push(TermReport {
+                term: term.clone(),
+                words: m.words,
+                status,
+            });
+        }
+
+        replacements.sort_by_key(|(r, _)| std::cmp::Reverse(r.start));
+        let words = word_indices(&tokens);
+        for (range, report) in replacements {
+            let first = words.iter().position(|w| *w == range.start);
+            let last = words.iter().rposition(|w| *w == range.end - 1);
+            if let (Some(first), Some(last)) = (first, last) {
+                // Punctuation attached to the replaced words is kept around the term.
+                let original = tokenizer.decode(&tokens[first..last + 1]);
+                let original = original.trim();
+                let is_punctuation = |c: char| !c.is_alphanumeric();
+                let start = original.len() - original.trim_start_matches(is_punctuation).len();
+                let end = original.trim_end_matches(is_punctuation).len().max(start);
+                let target = format!(
+                    "{}{}{}",
+                    &original[..start],
+                    terms[report].term.target,
+                    &original[end..]
+                );
+                tokens.splice(first..last + 1, tokenizer.encode(&target));
+            } else {
+                terms[report].status = TermStatus::Violated;
+            }
+        }
+        res.push(GlossaryTranslation { tokens, terms });
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn loads_csv_and_tbx() {
+        let glossary =
+            Glossary::from_csv_str("source,target\n# comment\n\"Rusty, Inc.\",Rusty KK\nGPU,GPU\n")
+                .unwrap();
+        assert_eq!(glossary.terms.len(), 2);
+        assert_eq!(glossary.terms[0].source, "Rusty, Inc.");
+
+        let tbx = r#"<tbx><text><body>
+            <termEntry id="1">
+                <langSet xml:lang="en-US"><tig><term>translation memory</term></tig></langSet>
+                <langSet xml:lang="de"><tig><term>Translation Memory</term></tig></langSet>
+            </termEntry>
+            <termEntry id="2">
+                <langSet xml:lang="en"><tig><term>only english</term></tig></langSet>
+            </termEntry>
+        </body></text></tbx>"#;
+        let glossary = Glossary::from_tbx_str(tbx, "en", "de").unwrap();
+        assert_eq!(
+            glossary.terms,
+            vec![Term {
+                source: "translation memory".to_string(),
+                target: "Translation Memory".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn finds_and_enforces_terms() {
+        let glossary = Glossary::new(vec![
+            Term {
+                source: "graphics card".to_string(),
+                target: "Grafikkarte".to_string(),
+            },
+            Term {
+                source: "card".to_string(),
+                target: "Karte".to_string(),
+            },
+            Term {
+                source: "driver".to_string(),
+                target: "DRIVER".to_string(),
+            },
+            Term {
+                source: "reboot".to_string(),
+                target: "Neustart".to_string(),
+            },
+        ]);
+        let input = WhitespaceTokenizer.encode("Update the graphics card driver and (reboot).");
+        let matches = glossary.find(&input);
+        assert_eq!(
+            matches,
+            vec![
+                TermMatch {
+                    term: 0,
+                    words: 2..4
+                },
+                TermMatch {
+                    term: 2,
+                    words: 4..5
+                },
+                TermMatch {
+                    term: 3,
+                    words: 6..7
+                }
+            ]
+        );
+        assert!(glossary.contains("die Autobahn (Auto).", "auto"));
+        assert!(!glossary.contains("die Autobahn", "Auto"));
+        assert!(!glossary.contains("concatenate", "cat"));
+
+        let mut translator = MockTranslator::upper();
+        let output = translate_with_glossary(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &glossary,
+            vec![input],
+            None,
+            None,
+            BatchType::Example,
+            Enforcement::Replace,
+        )
+        .unwrap();
+        assert_eq!(
+            WhitespaceTokenizer.decode(&output[0].tokens),
+            "UPDATE THE Grafikkarte DRIVER AND (Neustart)."
+        );
+        let status: Vec<_> = output[0].terms.iter().map(|v| v.status).collect();
+        assert_eq!(
+            status,
+            vec![
+                TermStatus::Replaced,
+                TermStatus::Present,
+                TermStatus::Replaced
+            ]
+        );
+    }
+}
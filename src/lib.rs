@@ -1,13 +1,45 @@
 #![allow(dead_code)]
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use cxx::{let_cxx_string, UniquePtr};
-use ffi::{CTranslateOptions, MyDataClass};
+use ffi::{CTranslateOptions, MyDataClass, MyResultClass, MyScoringClass};
 
 use crate::ffi::MyTranslator;
+use crate::reader::ModelReader;
 
-#[derive(Default)]
+pub mod alignment;
+pub mod back_translation;
+pub mod batching;
+pub mod bundle;
+pub mod cache;
+pub mod checked;
+pub mod document;
+pub mod eval;
+pub mod glossary;
+pub mod localization;
+pub mod markup;
+pub mod memory;
+pub mod metrics;
+pub mod model_bin;
+pub mod quality;
+pub mod quantize;
+pub mod reader;
+pub mod registry;
+pub mod reload;
+pub mod rerank;
+pub mod retry;
+pub mod segment;
+pub mod subtitle;
+#[cfg(test)]
+mod testing;
+pub mod tokenizer;
+pub mod verify;
+mod xml;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BatchType {
     #[default]
     Example,
@@ -15,11 +47,14 @@ pub enum BatchType {
 }
 
 impl BatchType {
-    fn to_bool(&self) -> bool {
+    fn to_bool(self) -> bool {
         matches!(self, BatchType::Example)
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct TranslationOptions {
     /// Beam size to use for beam search (set 1 to run greedy search).
     pub beam_size: usize,
@@ -112,6 +147,133 @@ impl Default for TranslationOptions {
     }
 }
 
+impl TranslationOptions {
+    /// Greedy search: a single hypothesis, no sampling.
+    pub fn greedy() -> Self {
+        Self {
+            beam_size: 1,
+            sampling_topk: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Beam search with the given beam size.
+    pub fn beam(beam_size: usize) -> Self {
+        Self {
+            beam_size,
+            ..Default::default()
+        }
+    }
+
+    /// Random sampling from the top K candidates with the given temperature.
+    pub fn sampling(sampling_topk: usize, sampling_temperature: f32) -> Self {
+        Self {
+            beam_size: 1,
+            sampling_topk,
+            sampling_temperature,
+            ..Default::default()
+        }
+    }
+
+    /// Beam search returning the `n` best hypotheses together with their scores.
+    pub fn diverse_nbest(n: usize) -> Self {
+        Self {
+            beam_size: n,
+            num_hypotheses: n,
+            return_scores: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Full result of the translation of one example.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TranslationResult {
+    /// Translation hypotheses, best first.
+    pub hypotheses: Vec<Vec<String>>,
+    /// Score of each hypothesis (empty unless `return_scores` is set).
+    pub scores: Vec<f32>,
+    /// Attention of each hypothesis, indexed as `[target token][source token]`
+    /// (empty unless `return_attention` is set, else one matrix per hypothesis).
+    pub attention: Vec<Vec<Vec<f32>>>,
+}
+
+impl TranslationResult {
+    /// Tokens of the best hypothesis.
+    pub fn output(&self) -> &[String] {
+        self.hypotheses.first().map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// Score of the best hypothesis, if scores were requested.
+    pub fn score(&self) -> Option<f32> {
+        self.scores.first().copied()
+    }
+}
+
+/// Score of a target sentence given its source.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScoringResult {
+    /// Scored target tokens, including the end token.
+    pub tokens: Vec<String>,
+    /// Log-prob of each token.
+    pub tokens_score: Vec<f32>,
+}
+
+impl ScoringResult {
+    /// Sum of the token log-probs.
+    pub fn cumulated_score(&self) -> f32 {
+        self.tokens_score.iter().sum()
+    }
+
+    /// Mean token log-prob, comparable across sentence lengths.
+    pub fn normalized_score(&self) -> f32 {
+        if self.tokens_score.is_empty() {
+            return 0.0;
+        }
+        self.cumulated_score() / self.tokens_score.len() as f32
+    }
+}
+
+/// Batch translation backend, implemented by [`CTranslator`].
+///
+/// The layers built on top of the translator (markup, ...) are generic over this trait.
+pub trait Translate {
+    fn translate_batch_results(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<TranslationResult>, String>;
+
+    fn translate_batch(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<Vec<String>>, String> {
+        Ok(self
+            .translate_batch_results(input, max_batch_size, options, batch_type)?
+            .into_iter()
+            .map(|v| v.output().to_vec())
+            .collect())
+    }
+}
+
+/// Forced-decoding backend that scores existing translations, implemented by [`CTranslator`].
+pub trait Score {
+    fn score_batch(
+        &mut self,
+        source: Vec<Vec<String>>,
+        target: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        batch_type: BatchType,
+    ) -> Result<Vec<ScoringResult>, String>;
+}
+
 #[cxx::bridge()]
 mod ffi {
 
@@ -120,11 +282,21 @@ mod ffi {
         type MyTranslator;
         type MyDataClass;
         type CTranslateOptions;
+        type MyResultClass;
+        type MyModelReader;
+        type MyScoringClass;
         fn new_translator(
             model: &CxxString,
             use_gpu: bool,
             compressed: bool,
         ) -> Result<UniquePtr<MyTranslator>>;
+        fn new_model_reader(model_id: &CxxString) -> UniquePtr<MyModelReader>;
+        fn registerFile(self: Pin<&mut MyModelReader>, filename: &str, content: &[u8]);
+        fn new_translator_from_reader(
+            reader: Pin<&mut MyModelReader>,
+            use_gpu: bool,
+            compressed: bool,
+        ) -> Result<UniquePtr<MyTranslator>>;
         fn translate_batch(
             self: Pin<&mut MyTranslator>,
             data: &MyDataClass,
@@ -132,6 +304,13 @@ mod ffi {
             max_batch_size: usize,
             batch_type_example: bool,
         ) -> Result<UniquePtr<MyDataClass>>;
+        fn translate_batch_results(
+            self: Pin<&mut MyTranslator>,
+            data: &MyDataClass,
+            options: &CTranslateOptions,
+            max_batch_size: usize,
+            batch_type_example: bool,
+        ) -> Result<UniquePtr<MyResultClass>>;
         fn translate_batch_target(
             self: Pin<&mut MyTranslator>,
             data: &MyDataClass,
@@ -140,10 +319,39 @@ mod ffi {
             max_batch_size: usize,
             batch_type_example: bool,
         ) -> Result<UniquePtr<MyDataClass>>;
+        fn score_batch(
+            self: Pin<&mut MyTranslator>,
+            source: &MyDataClass,
+            target: &MyDataClass,
+            max_batch_size: usize,
+            batch_type_example: bool,
+        ) -> Result<UniquePtr<MyScoringClass>>;
         fn new_data() -> UniquePtr<MyDataClass>;
         fn getLength(self: &MyDataClass) -> usize;
         fn pushData(self: Pin<&mut MyDataClass>, item: Vec<String>);
         fn getData(self: &MyDataClass, data: usize) -> Result<Vec<String>>;
+        fn getLength(self: &MyResultClass) -> usize;
+        fn getNumHypotheses(self: &MyResultClass, index: usize) -> Result<usize>;
+        fn getHypothesis(
+            self: &MyResultClass,
+            index: usize,
+            hypothesis: usize,
+        ) -> Result<Vec<String>>;
+        fn getScores(self: &MyResultClass, index: usize) -> Result<Vec<f32>>;
+        fn getAttentionLength(
+            self: &MyResultClass,
+            index: usize,
+            hypothesis: usize,
+        ) -> Result<usize>;
+        fn getAttentionRow(
+            self: &MyResultClass,
+            index: usize,
+            hypothesis: usize,
+            row: usize,
+        ) -> Result<Vec<f32>>;
+        fn getLength(self: &MyScoringClass) -> usize;
+        fn getTokens(self: &MyScoringClass, index: usize) -> Result<Vec<String>>;
+        fn getTokenScores(self: &MyScoringClass, index: usize) -> Result<Vec<f32>>;
         #[allow(clippy::too_many_arguments)]
         fn get_options(
             beam_size: usize,
@@ -171,9 +379,12 @@ mod ffi {
     }
 }
 
+unsafe impl Send for ffi::MyTranslator {}
 unsafe impl Sync for ffi::MyTranslator {}
 unsafe impl Sync for ffi::MyDataClass {}
 unsafe impl Sync for ffi::CTranslateOptions {}
+unsafe impl Sync for ffi::MyResultClass {}
+unsafe impl Sync for ffi::MyScoringClass {}
 
 pub struct CTranslator {
     model: UniquePtr<MyTranslator>,
@@ -181,12 +392,43 @@ pub struct CTranslator {
 
 impl CTranslator {
     pub fn new(path: PathBuf, use_gpu: bool, compressed: bool) -> Result<Self, String> {
-        let path = path.to_str().map(|v| v.to_string()).unwrap();
+        let path = path
+            .to_str()
+            .map(|v| v.to_string())
+            .ok_or_else(|| format!("model path is not valid UTF-8: {}", path.display()))?;
         let_cxx_string!(model = path);
         let model = ffi::new_translator(&model, use_gpu, compressed).map_err(|e| e.to_string())?;
         Ok(Self { model })
     }
 
+    /// Loads a model from memory, e.g. from a decrypted archive, without writing it to disk.
+    pub fn from_reader<R: ModelReader>(
+        reader: &mut R,
+        use_gpu: bool,
+        compressed: bool,
+    ) -> Result<Self, String> {
+        let files = reader::read_model_files(reader)?;
+        let_cxx_string!(model_id = reader.model_id());
+        let mut memory = ffi::new_model_reader(&model_id);
+        for (name, content) in &files {
+            memory.pin_mut().registerFile(name, content);
+        }
+        let model = ffi::new_translator_from_reader(memory.pin_mut(), use_gpu, compressed)
+            .map_err(|e| e.to_string())?;
+        Ok(Self { model })
+    }
+
+    /// Loads a model from the contents of its files, keyed by file name (`model.bin`,
+    /// `config.json`, vocabularies).
+    pub fn from_buffers(
+        files: HashMap<String, Vec<u8>>,
+        use_gpu: bool,
+        compressed: bool,
+    ) -> Result<Self, String> {
+        let mut files = files;
+        Self::from_reader(&mut files, use_gpu, compressed)
+    }
+
     pub fn translate_batch(
         &mut self,
         input: Vec<Vec<String>>,
@@ -210,6 +452,32 @@ impl CTranslator {
         Self::extract_output(v)
     }
 
+    /// Like [`CTranslator::translate_batch`], but returns all hypotheses together with
+    /// the scores and attention requested in the options.
+    pub fn translate_batch_results(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<TranslationResult>, String> {
+        let data = Self::generate_input(input)?;
+        let with_attention = options.as_ref().is_some_and(|o| o.return_attention);
+        let options = self.get_options(options);
+        let v = self
+            .model
+            .as_mut()
+            .ok_or_else(|| "mut model is none".to_string())?
+            .translate_batch_results(
+                &data,
+                &options,
+                max_batch_size.unwrap_or(0),
+                batch_type.to_bool(),
+            )
+            .map_err(|e| e.to_string())?;
+        Self::extract_results(v, with_attention)
+    }
+
     pub fn translate_batch_target(
         &mut self,
         input: Vec<Vec<String>>,
@@ -235,6 +503,37 @@ impl CTranslator {
         Self::extract_output(v)
     }
 
+    /// Scores each target sentence given its source (forced decoding).
+    pub fn score_batch(
+        &mut self,
+        source: Vec<Vec<String>>,
+        target: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        batch_type: BatchType,
+    ) -> Result<Vec<ScoringResult>, String> {
+        if source.len() != target.len() {
+            return Err(format!(
+                "{} source sentences but {} target sentences",
+                source.len(),
+                target.len()
+            ));
+        }
+        let source = Self::generate_input(source)?;
+        let target = Self::generate_input(target)?;
+        let v = self
+            .model
+            .as_mut()
+            .ok_or_else(|| "mut model is none".to_string())?
+            .score_batch(
+                &source,
+                &target,
+                max_batch_size.unwrap_or(0),
+                batch_type.to_bool(),
+            )
+            .map_err(|e| e.to_string())?;
+        Self::extract_scores(v)
+    }
+
     fn generate_input(input: Vec<Vec<String>>) -> Result<UniquePtr<MyDataClass>, String> {
         let mut data = ffi::new_data();
         for item in input {
@@ -254,6 +553,50 @@ impl CTranslator {
         Ok(res)
     }
 
+    fn extract_results(
+        v: UniquePtr<MyResultClass>,
+        with_attention: bool,
+    ) -> Result<Vec<TranslationResult>, String> {
+        let mut res = vec![];
+        for index in 0..v.getLength() {
+            let num_hypotheses = v.getNumHypotheses(index).map_err(|e| e.to_string())?;
+            let mut result = TranslationResult {
+                scores: v.getScores(index).map_err(|e| e.to_string())?,
+                ..Default::default()
+            };
+            for hypothesis in 0..num_hypotheses {
+                result.hypotheses.push(
+                    v.getHypothesis(index, hypothesis)
+                        .map_err(|e| e.to_string())?,
+                );
+                if with_attention {
+                    // One matrix per hypothesis, even empty, to keep them aligned by index.
+                    let rows = v
+                        .getAttentionLength(index, hypothesis)
+                        .map_err(|e| e.to_string())?;
+                    let attention = (0..rows)
+                        .map(|row| v.getAttentionRow(index, hypothesis, row))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| e.to_string())?;
+                    result.attention.push(attention);
+                }
+            }
+            res.push(result);
+        }
+        Ok(res)
+    }
+
+    fn extract_scores(v: UniquePtr<MyScoringClass>) -> Result<Vec<ScoringResult>, String> {
+        let mut res = vec![];
+        for index in 0..v.getLength() {
+            res.push(ScoringResult {
+                tokens: v.getTokens(index).map_err(|e| e.to_string())?,
+                tokens_score: v.getTokenScores(index).map_err(|e| e.to_string())?,
+            });
+        }
+        Ok(res)
+    }
+
     fn get_options(&self, options: Option<TranslationOptions>) -> UniquePtr<CTranslateOptions> {
         let o = options.unwrap_or_default();
         ffi::get_options(
@@ -282,28 +625,159 @@ impl CTranslator {
     }
 }
 
+impl Translate for CTranslator {
+    fn translate_batch_results(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<TranslationResult>, String> {
+        CTranslator::translate_batch_results(self, input, max_batch_size, options, batch_type)
+    }
+
+    fn translate_batch(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<Vec<String>>, String> {
+        CTranslator::translate_batch(self, input, max_batch_size, options, batch_type)
+    }
+}
+
+impl Score for CTranslator {
+    fn score_batch(
+        &mut self,
+        source: Vec<Vec<String>>,
+        target: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        batch_type: BatchType,
+    ) -> Result<Vec<ScoringResult>, String> {
+        CTranslator::score_batch(self, source, target, max_batch_size, batch_type)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::fs;
 
     use super::*;
 
+    fn tokens(text: &str) -> Vec<String> {
+        text.split(' ').map(|v| v.to_string()).collect()
+    }
+
     #[test]
     fn it_works() {
-        let model = CTranslator::new(
-            PathBuf::from_str("jparacrawl/base-en-ja").unwrap(),
-            false,
-            true,
-        );
+        let dir = testing::tiny_model();
+        let model = CTranslator::new(dir.clone(), false, true);
         assert!(model.is_ok());
-        let tokens = ["▁H", "ell", "o", "▁world", "!"]
-            .into_iter()
-            .map(|v| v.to_string())
-            .collect();
-        let v = model
-            .unwrap()
-            .translate_batch(vec![tokens], None, None, BatchType::Example);
+        let v = model.unwrap().translate_batch(
+            vec![tokens("▁hello ▁world !")],
+            None,
+            None,
+            BatchType::Example,
+        );
         assert!(v.is_ok());
         println!("{:?}", v);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn options_and_target_prefix() {
+        let dir = testing::tiny_model();
+        let mut model = CTranslator::new(dir.clone(), false, false).unwrap();
+        let options = TranslationOptions {
+            num_hypotheses: 2,
+            max_decoding_length: 3,
+            return_attention: true,
+            ..TranslationOptions::beam(2)
+        };
+        let input = vec![tokens("▁hello ▁world"), tokens("▁the ▁world ▁is")];
+        let output = model
+            .translate_batch_results(input.clone(), Some(1), Some(options), BatchType::Example)
+            .unwrap();
+        assert_eq!(output.len(), 2);
+        for result in &output {
+            assert_eq!(result.hypotheses.len(), 2);
+            assert_eq!(result.attention.len(), 2);
+            assert!(result.output().len() <= 3);
+        }
+
+        let output = model
+            .translate_batch_target(
+                input,
+                None,
+                BatchType::Tokens,
+                Some(TranslationOptions::greedy()),
+                vec!["▁a".to_string(), "▁the".to_string()],
+            )
+            .unwrap();
+        assert_eq!(output[0][0], "▁a");
+        assert_eq!(output[1][0], "▁the");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scores_targets() {
+        let dir = testing::tiny_model();
+        let mut model = CTranslator::new(dir.clone(), false, false).unwrap();
+        let scores = model
+            .score_batch(
+                vec![tokens("▁hello ▁world")],
+                vec![tokens("▁hello ▁world")],
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].tokens.len(), scores[0].tokens_score.len());
+        assert!(scores[0].normalized_score() <= 0.0);
+        assert!(model
+            .score_batch(vec![tokens("▁hello")], vec![], None, BatchType::Example)
+            .is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_load_errors() {
+        let dir = testing::tiny_model();
+        fs::remove_file(dir.join("model.bin")).unwrap();
+        assert!(CTranslator::new(dir.clone(), false, false).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn presets() {
+        assert_eq!(TranslationOptions::greedy().beam_size, 1);
+        assert_eq!(TranslationOptions::beam(5).beam_size, 5);
+        let sampling = TranslationOptions::sampling(10, 0.7);
+        assert_eq!((sampling.beam_size, sampling.sampling_topk), (1, 10));
+        let nbest = TranslationOptions::diverse_nbest(4);
+        assert_eq!((nbest.beam_size, nbest.num_hypotheses), (4, 4));
+        assert!(nbest.return_scores);
+        assert_eq!(
+            nbest.max_input_length,
+            TranslationOptions::default().max_input_length
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn options_from_partial_config() {
+        let options: TranslationOptions =
+            serde_json::from_str(r#"{"beam_size": 4, "return_scores": true}"#).unwrap();
+        assert_eq!(
+            options,
+            TranslationOptions {
+                beam_size: 4,
+                return_scores: true,
+                ..Default::default()
+            }
+        );
+        let batch_type: BatchType = serde_json::from_str(r#""Tokens""#).unwrap();
+        assert_eq!(batch_type, BatchType::Tokens);
     }
 }
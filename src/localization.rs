@@ -0,0 +1,770 @@
+//! Gettext PO and XLIFF 1.2/2.0 localization files.
+//!
+//! Untranslated units are translated with [`translate_markup`], so placeholders (`%s`, `{name}`)
+//! and inline codes are kept, and marked for review: the `fuzzy` flag in PO files, the review state
+//! in XLIFF.
+
+use std::iter;
+use std::ops::Range;
+
+use crate::markup::{translate_markup, MarkupTranslation};
+use crate::tokenizer::Tokenizer;
+use crate::xml;
+use crate::{BatchType, Translate, TranslationOptions};
+
+/// Result of translating a localization file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LocalizationReport {
+    pub translated: usize,
+    /// Units (msgid or XLIFF id) whose placeholders or tags differ from the source.
+    pub invalid: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoEntry {
+    /// Comment lines (`#`, `#.`, `#:`, `#|`, `#~`) as written, except the flags.
+    pub comments: Vec<String>,
+    pub flags: Vec<String>,
+    pub msgctxt: Option<String>,
+    pub msgid: String,
+    pub msgid_plural: Option<String>,
+    /// `msgstr`, or `msgstr[n]` of plural entries.
+    pub msgstr: Vec<String>,
+}
+
+impl PoEntry {
+    /// The entry with an empty msgid holding the metadata of the file.
+    pub fn is_header(&self) -> bool {
+        self.msgid.is_empty() && self.msgctxt.is_none() && !self.msgstr.is_empty()
+    }
+
+    pub fn is_translated(&self) -> bool {
+        self.msgstr.iter().any(|v| !v.is_empty())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoFile {
+    pub entries: Vec<PoEntry>,
+}
+
+impl PoFile {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        enum Field {
+            Context,
+            Id,
+            Plural,
+            Str(usize),
+        }
+        let mut entries = vec![];
+        let mut entry = PoEntry::default();
+        let mut field = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |e: String| format!("line {}: {}", i + 1, e);
+            // A comment or a new msgid after a msgstr starts the next entry.
+            let starts_entry = line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("msgctxt")
+                || line.starts_with("msgid ");
+            if starts_entry && matches!(field, Some(Field::Str(_))) || line.is_empty() {
+                if entry != PoEntry::default() {
+                    entries.push(std::mem::take(&mut entry));
+                }
+                field = None;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(flags) = line.strip_prefix("#,") {
+                entry.flags.extend(
+                    flags
+                        .split(',')
+                        .map(|v| v.trim().to_string())
+                        .filter(|v| !v.is_empty()),
+                );
+                continue;
+            }
+            if line.starts_with('#') {
+                entry.comments.push(line.to_string());
+                continue;
+            }
+            if line.starts_with('"') {
+                let value = unquote(line).map_err(error)?;
+                match field {
+                    Some(Field::Context) => entry.msgctxt.get_or_insert_default().push_str(&value),
+                    Some(Field::Id) => entry.msgid.push_str(&value),
+                    Some(Field::Plural) => {
+                        entry.msgid_plural.get_or_insert_default().push_str(&value)
+                    }
+                    Some(Field::Str(n)) => entry.msgstr[n].push_str(&value),
+                    None => return Err(error("string outside of an entry".to_string())),
+                }
+                continue;
+            }
+            let (keyword, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = unquote(value.trim()).map_err(error)?;
+            field = Some(match keyword {
+                "msgctxt" => {
+                    entry.msgctxt = Some(value);
+                    Field::Context
+                }
+                "msgid" => {
+                    entry.msgid = value;
+                    Field::Id
+                }
+                "msgid_plural" => {
+                    entry.msgid_plural = Some(value);
+                    Field::Plural
+                }
+                _ => {
+                    let n = match keyword.strip_prefix("msgstr") {
+                        Some("") => 0,
+                        Some(index) => index
+                            .strip_prefix('[')
+                            .and_then(|v| v.strip_suffix(']'))
+                            .and_then(|v| v.parse().ok())
+                            .ok_or_else(|| error(format!("bad keyword {}", keyword)))?,
+                        None => return Err(error(format!("unexpected {}", line))),
+                    };
+                    if entry.msgstr.len() <= n {
+                        entry.msgstr.resize(n + 1, String::new());
+                    }
+                    entry.msgstr[n] = value;
+                    Field::Str(n)
+                }
+            });
+        }
+        if entry != PoEntry::default() {
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            // Flags go before the previous-msgid (`#|`) and obsolete (`#~`) comments.
+            let late = |v: &&String| v.starts_with("#|") || v.starts_with("#~");
+            for comment in entry.comments.iter().filter(|v| !late(v)) {
+                out.push_str(comment);
+                out.push('\n');
+            }
+            if !entry.flags.is_empty() {
+                out.push_str(&format!("#, {}\n", entry.flags.join(", ")));
+            }
+            for comment in entry.comments.iter().filter(late) {
+                out.push_str(comment);
+                out.push('\n');
+            }
+            if entry.msgid.is_empty() && entry.msgstr.is_empty() && entry.msgctxt.is_none() {
+                continue;
+            }
+            if let Some(context) = &entry.msgctxt {
+                write_string(&mut out, "msgctxt", context);
+            }
+            write_string(&mut out, "msgid", &entry.msgid);
+            match &entry.msgid_plural {
+                Some(plural) => {
+                    write_string(&mut out, "msgid_plural", plural);
+                    for (n, value) in entry.msgstr.iter().enumerate() {
+                        write_string(&mut out, &format!("msgstr[{}]", n), value);
+                    }
+                }
+                None => write_string(
+                    &mut out,
+                    "msgstr",
+                    entry.msgstr.first().map_or("", |v| v.as_str()),
+                ),
+            }
+        }
+        out
+    }
+
+    /// Number of plural forms of the target language, from the `Plural-Forms` header (2 if
+    /// missing).
+    pub fn nplurals(&self) -> usize {
+        self.entries
+            .iter()
+            .find(|v| v.is_header())
+            .and_then(|header| {
+                let text = &header.msgstr[0];
+                let at = text.find("nplurals=")? + "nplurals=".len();
+                text[at..]
+                    .chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .parse()
+                    .ok()
+            })
+            .unwrap_or(2)
+    }
+}
+
+/// Value of a quoted PO string.
+fn unquote(text: &str) -> Result<String, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string: {}", text))?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    Ok(out)
+}
+
+/// Writes a keyword and its string, one line per `\n` for multi-line strings like gettext does.
+fn write_string(out: &mut String, keyword: &str, value: &str) {
+    let quote = |v: &str| {
+        let escaped = v
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t")
+            .replace('\r', "\\r");
+        format!("\"{}\"", escaped)
+    };
+    if value.trim_end_matches('\n').contains('\n') {
+        out.push_str(&format!("{} \"\"\n", keyword));
+        for line in value.split_inclusive('\n') {
+            out.push_str(&quote(line));
+            out.push('\n');
+        }
+    } else {
+        out.push_str(&format!("{} {}\n", keyword, quote(value)));
+    }
+}
+
+/// Translates the untranslated entries of a PO file and flags them `fuzzy`.
+///
+/// Plural entries get the translated msgid as first form and the translated msgid_plural as the
+/// other forms, or only the latter for languages with a single form.
+pub fn translate_po<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    po: &mut PoFile,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<LocalizationReport, String> {
+    let nplurals = po.nplurals();
+    let pending: Vec<usize> = (0..po.entries.len())
+        .filter(|&i| !po.entries[i].msgid.is_empty() && !po.entries[i].is_translated())
+        .collect();
+    let texts: Vec<String> = pending
+        .iter()
+        .flat_map(|&i| iter::once(&po.entries[i].msgid).chain(&po.entries[i].msgid_plural))
+        .cloned()
+        .collect();
+    let mut translations = translate_texts(
+        translator,
+        tokenizer,
+        &texts,
+        max_batch_size,
+        options,
+        batch_type,
+    )?
+    .into_iter();
+
+    let mut report = LocalizationReport::default();
+    for i in pending {
+        let entry = &mut po.entries[i];
+        let singular = translations.next().unwrap_or_default();
+        let plural = entry
+            .msgid_plural
+            .as_ref()
+            .map(|_| translations.next().unwrap_or_default());
+        if !singular.is_valid() || plural.as_ref().is_some_and(|v| !v.is_valid()) {
+            report.invalid.push(entry.msgid.clone());
+        }
+        entry.msgstr = match plural {
+            Some(plural) if nplurals <= 1 => vec![plural.text],
+            Some(plural) => iter::once(singular.text)
+                .chain(iter::repeat_n(plural.text, nplurals - 1))
+                .collect(),
+            None => vec![singular.text],
+        };
+        if !entry.flags.iter().any(|v| v == "fuzzy") {
+            entry.flags.insert(0, "fuzzy".to_string());
+        }
+        report.translated += 1;
+    }
+    Ok(report)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XliffVersion {
+    V1_2,
+    V2_0,
+}
+
+/// A `trans-unit` (1.2) or `segment` (2.0).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XliffUnit {
+    pub id: String,
+    /// Raw XML content of the source, with inline codes.
+    pub source: String,
+    /// Raw XML content of the target.
+    pub target: Option<String>,
+    /// Mark the target as machine translated and needing review when written.
+    pub needs_review: bool,
+}
+
+/// An XLIFF document. Only the targets and their state are rewritten, the rest of the document is
+/// kept as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Xliff {
+    pub version: XliffVersion,
+    pub units: Vec<XliffUnit>,
+    text: String,
+    ranges: Vec<Range<usize>>,
+}
+
+impl Xliff {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let root = xml::elements(text, "xliff")
+            .into_iter()
+            .next()
+            .ok_or("not an XLIFF document")?;
+        let version = match root.attribute("version").as_deref() {
+            Some("1.0" | "1.1" | "1.2") => XliffVersion::V1_2,
+            Some(v) if v.starts_with("2.") => XliffVersion::V2_0,
+            v => return Err(format!("unsupported XLIFF version {:?}", v)),
+        };
+        let mut units = vec![];
+        let mut ranges = vec![];
+        let mut push = |id: String, element: &xml::Element, offset: usize| {
+            let Some(source) = element.child("source") else {
+                return;
+            };
+            units.push(XliffUnit {
+                id,
+                source: source.inner.to_string(),
+                target: own_part(element.inner, "target").map(|v| v.inner.to_string()),
+                needs_review: false,
+            });
+            ranges.push(offset + element.range.start..offset + element.range.end);
+        };
+        match version {
+            XliffVersion::V1_2 => {
+                for unit in xml::elements(text, "trans-unit") {
+                    if unit.attribute("translate").as_deref() != Some("no") {
+                        push(unit.attribute("id").unwrap_or_default(), &unit, 0);
+                    }
+                }
+            }
+            XliffVersion::V2_0 => {
+                for unit in xml::elements(text, "unit") {
+                    if unit.attribute("translate").as_deref() == Some("no") {
+                        continue;
+                    }
+                    let id = unit.attribute("id").unwrap_or_default();
+                    let offset = unit.range.start + unit.start_tag.len();
+                    for segment in unit.children("segment") {
+                        let id = segment.attribute("id").unwrap_or_else(|| id.clone());
+                        push(id, &segment, offset);
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            version,
+            units,
+            text: text.to_string(),
+            ranges,
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for (unit, range) in self.units.iter().zip(&self.ranges) {
+            out.push_str(&self.text[last..range.start]);
+            let element = &self.text[range.clone()];
+            match &unit.target {
+                Some(target) => out.push_str(&self.render_unit(element, target, unit.needs_review)),
+                None => out.push_str(element),
+            }
+            last = range.end;
+        }
+        out.push_str(&self.text[last..]);
+        out
+    }
+
+    /// The `trans-unit` or `segment` element with its target replaced.
+    fn render_unit(&self, element: &str, target: &str, needs_review: bool) -> String {
+        let mut element = element.to_string();
+        if needs_review && self.version == XliffVersion::V2_0 {
+            let end = element.find('>').unwrap_or(0);
+            let start_tag = with_attribute(&element[..=end], "state", "translated");
+            let start_tag = with_attribute(&start_tag, "subState", "rustyctranslate2:needs-review");
+            element.replace_range(..=end, &start_tag);
+        }
+
+        let existing = own_part(&element, "target").map(|v| (v.start_tag.to_string(), v.range));
+        let (start_tag, range) = match existing {
+            Some((start_tag, range)) => (start_tag.replace("/>", ">"), range),
+            None => {
+                let Some(source) = xml::elements(&element, "source").into_iter().next() else {
+                    return element;
+                };
+                let end = source.range.end;
+                // Same indentation as the source.
+                let line = &element[..source.range.start];
+                let indent = &line[line.rfind('\n').map_or(line.len(), |v| v + 1)..];
+                let mut start_tag = String::new();
+                if !indent.is_empty() && indent.trim().is_empty() {
+                    start_tag.push('\n');
+                    start_tag.push_str(indent);
+                }
+                start_tag.push_str("<target>");
+                (start_tag, end..end)
+            }
+        };
+        let start_tag = if needs_review && self.version == XliffVersion::V1_2 {
+            let start_tag = with_attribute(&start_tag, "state", "needs-review-translation");
+            with_attribute(&start_tag, "state-qualifier", "mt-suggestion")
+        } else {
+            start_tag
+        };
+        element.replace_range(range, &format!("{}{}</target>", start_tag, target));
+        element
+    }
+}
+
+/// First element with the given name, ignoring the alternative translations (`alt-trans`).
+fn own_part<'a>(text: &'a str, name: &str) -> Option<xml::Element<'a>> {
+    let own = text.split("<alt-trans").next().unwrap_or(text);
+    xml::elements(own, name).into_iter().next()
+}
+
+/// Sets an attribute of a start tag, replacing its value if present.
+fn with_attribute(start_tag: &str, name: &str, value: &str) -> String {
+    let attribute = format!(" {}=", name);
+    if let Some(at) = start_tag.find(&attribute) {
+        let value_start = at + attribute.len();
+        let quote = start_tag[value_start..].chars().next().unwrap_or('"');
+        let value_end = start_tag[value_start + 1..]
+            .find(quote)
+            .map_or(start_tag.len(), |v| value_start + 1 + v + 1);
+        return format!(
+            "{}{}\"{}\"{}",
+            &start_tag[..at],
+            attribute,
+            xml::escape(value),
+            &start_tag[value_end..]
+        );
+    }
+    let end = start_tag.len() - if start_tag.ends_with("/>") { 2 } else { 1 };
+    format!(
+        "{} {}=\"{}\"{}",
+        &start_tag[..end],
+        name,
+        xml::escape(value),
+        &start_tag[end..]
+    )
+}
+
+/// Translates the units without target (or with an empty one) and marks them for review.
+pub fn translate_xliff<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    xliff: &mut Xliff,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<LocalizationReport, String> {
+    let pending: Vec<usize> = (0..xliff.units.len())
+        .filter(|&i| {
+            xliff.units[i]
+                .target
+                .as_ref()
+                .is_none_or(|v| split_inline(v).0.trim().is_empty())
+        })
+        .collect();
+    let sources: Vec<(String, Vec<String>)> = pending
+        .iter()
+        .map(|&i| split_inline(&xliff.units[i].source))
+        .collect();
+    let texts: Vec<String> = sources.iter().map(|(text, _)| text.clone()).collect();
+    let translations = translate_texts(
+        translator,
+        tokenizer,
+        &texts,
+        max_batch_size,
+        options,
+        batch_type,
+    )?;
+
+    let mut report = LocalizationReport::default();
+    for ((i, (_, codes)), translation) in pending.into_iter().zip(&sources).zip(translations) {
+        let unit = &mut xliff.units[i];
+        if !translation.is_valid() {
+            report.invalid.push(unit.id.clone());
+        }
+        unit.target = Some(join_inline(&translation.text, codes));
+        unit.needs_review = true;
+        report.translated += 1;
+    }
+    Ok(report)
+}
+
+/// Unescaped text of XML content (XLIFF, HTML) with the inline codes left in, and the inline codes.
+pub(crate) fn split_inline(content: &str) -> (String, Vec<String>) {
+    let (mut text, mut codes) = (String::new(), vec![]);
+    let mut rest = content;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&xml::unescape(&rest[..start]));
+        let end = rest[start..]
+            .find('>')
+            .map_or(rest.len(), |v| start + v + 1);
+        codes.push(rest[start..end].to_string());
+        text.push_str(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    text.push_str(&xml::unescape(rest));
+    (text, codes)
+}
+
+/// Inverse of [`split_inline`]: escapes the text around the inline codes.
+pub(crate) fn join_inline(text: &str, codes: &[String]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some(code) = codes.iter().find(|v| rest.starts_with(v.as_str())) {
+            out.push_str(code);
+            rest = &rest[code.len()..];
+            continue;
+        }
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Translates texts line by line in one call, keeping the empty lines and the whitespace around
+/// every line.
+pub(crate) fn translate_texts<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    texts: &[String],
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<Vec<MarkupTranslation>, String> {
+    let lines: Vec<String> = texts
+        .iter()
+        .flat_map(|v| v.split('\n'))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    let mut translations = translate_markup(
+        translator,
+        tokenizer,
+        &lines,
+        max_batch_size,
+        options,
+        batch_type,
+    )?
+    .into_iter();
+    Ok(texts
+        .iter()
+        .map(|text| {
+            let mut res = MarkupTranslation {
+                aligned: true,
+                ..Default::default()
+            };
+            let mut out = vec![];
+            for line in text.split('\n') {
+                let trimmed = line.trim();
+                if trimmed.is_empty() {
+                    out.push(line.to_string());
+                    continue;
+                }
+                let translation = translations.next().unwrap_or_default();
+                let before = &line[..line.len() - line.trim_start().len()];
+                let after = &line[line.trim_end().len()..];
+                out.push(format!("{}{}{}", before, translation.text, after));
+                res.missing.extend(translation.missing);
+                res.unexpected.extend(translation.unexpected);
+                res.aligned &= translation.aligned;
+            }
+            res.text = out.join("\n");
+            res
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    const PO: &str = r#"msgid ""
+msgstr ""
+"Language: pl\n"
+"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : 1);\n"
+
+#: src/main.c:10
+#, c-format
+msgid "Hello %s"
+msgstr ""
+
+msgid "done"
+msgstr "gotowe"
+
+#, fuzzy
+#| msgid "One file"
+msgctxt "files"
+msgid "one file"
+msgid_plural "{count} files"
+msgstr[0] ""
+msgstr[1] ""
+
+msgid "two\n"
+"lines\n"
+msgstr ""
+
+#~ msgid "old"
+#~ msgstr "stary"
+"#;
+
+    #[test]
+    fn translates_po() {
+        let mut po = PoFile::parse(PO).unwrap();
+        assert_eq!(po.entries.len(), 6);
+        assert_eq!(po.nplurals(), 3);
+        assert_eq!(po.entries[4].msgid, "two\nlines\n");
+
+        let mut translator = MockTranslator::upper();
+        let report = translate_po(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &mut po,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(report.translated, 3);
+        assert!(report.invalid.is_empty());
+        assert_eq!(translator.batches.len(), 1);
+        assert_eq!(
+            po.render(),
+            r#"msgid ""
+msgstr ""
+"Language: pl\n"
+"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : 1);\n"
+
+#: src/main.c:10
+#, fuzzy, c-format
+msgid "Hello %s"
+msgstr "HELLO %s"
+
+msgid "done"
+msgstr "gotowe"
+
+#, fuzzy
+#| msgid "One file"
+msgctxt "files"
+msgid "one file"
+msgid_plural "{count} files"
+msgstr[0] "ONE FILE"
+msgstr[1] "{count} FILES"
+msgstr[2] "{count} FILES"
+
+#, fuzzy
+msgid ""
+"two\n"
+"lines\n"
+msgstr ""
+"TWO\n"
+"LINES\n"
+
+#~ msgid "old"
+#~ msgstr "stary"
+"#
+        );
+        assert_eq!(PoFile::parse(&po.render()).unwrap(), po);
+    }
+
+    #[test]
+    fn translates_xliff() {
+        let v12 = r#"<xliff version="1.2"><file source-language="en" target-language="de">
+  <body>
+    <trans-unit id="greeting">
+      <source>Hello <x id="1"/> &amp; bye</source>
+    </trans-unit>
+    <trans-unit id="empty"><source>go</source><target/></trans-unit>
+    <trans-unit id="done"><source>done</source><target>fertig</target></trans-unit>
+    <trans-unit id="code" translate="no"><source>x</source></trans-unit>
+  </body>
+</file></xliff>"#;
+        let mut xliff = Xliff::parse(v12).unwrap();
+        assert_eq!(xliff.units.len(), 3);
+        let mut translator = MockTranslator::upper();
+        let report = translate_xliff(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &mut xliff,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(report.translated, 2);
+        assert_eq!(
+            xliff.render(),
+            r#"<xliff version="1.2"><file source-language="en" target-language="de">
+  <body>
+    <trans-unit id="greeting">
+      <source>Hello <x id="1"/> &amp; bye</source>
+      <target state="needs-review-translation" state-qualifier="mt-suggestion">HELLO <x id="1"/> &amp; BYE</target>
+    </trans-unit>
+    <trans-unit id="empty"><source>go</source><target state="needs-review-translation" state-qualifier="mt-suggestion">GO</target></trans-unit>
+    <trans-unit id="done"><source>done</source><target>fertig</target></trans-unit>
+    <trans-unit id="code" translate="no"><source>x</source></trans-unit>
+  </body>
+</file></xliff>"#
+        );
+
+        let v20 = r#"<xliff version="2.0" srcLang="en" trgLang="de"><file id="f">
+<unit id="u1"><segment state="initial"><source>Hi {name}!</source></segment></unit>
+</file></xliff>"#;
+        let mut xliff = Xliff::parse(v20).unwrap();
+        translate_xliff(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &mut xliff,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(xliff.units[0].id, "u1");
+        assert!(xliff.render().contains(
+            r#"<segment state="translated" subState="rustyctranslate2:needs-review"><source>Hi {name}!</source><target>HI {name}!</target></segment>"#
+        ));
+    }
+}
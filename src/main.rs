@@ -0,0 +1,155 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rustyctranslate2::eval::{self, Metric, Scores};
+use rustyctranslate2::model_bin::{self, DataType, ModelBin};
+use rustyctranslate2::quantize::{quantize_model_dir, Quantization};
+use rustyctranslate2::tokenizer::{Tokenizer, WhitespaceTokenizer};
+use rustyctranslate2::{BatchType, CTranslator};
+
+const USAGE: &str = "usage: rustyctranslate2 <command>
+
+commands:
+  inspect <model dir or model.bin>    list the variables of a converted model
+  quantize <input dir> <output dir> <int8|float16|float32>
+                                      rewrite a model with another weight type
+  eval <model dir> <source file> <reference file> [<baseline model dir>]
+                                      translate a test set and score it with BLEU, chrF++ and
+                                      TER, compared to the baseline with paired bootstrap
+                                      resampling; source lines hold the model tokens separated
+                                      by spaces, references plain text";
+
+/// Resampled test sets for the significance tests of `eval`.
+const BOOTSTRAP_SAMPLES: usize = 1000;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.iter().map(|v| v.as_str()).collect::<Vec<_>>()[..] {
+        ["inspect", path] => inspect(PathBuf::from(path)),
+        ["quantize", input, output, quantization] => quantization
+            .parse::<Quantization>()
+            .and_then(|v| quantize_model_dir(input, output, v)),
+        ["eval", model, source, reference] => evaluate(model, source, reference, None),
+        ["eval", model, source, reference, baseline] => {
+            evaluate(model, source, reference, Some(baseline))
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn inspect(mut path: PathBuf) -> Result<(), String> {
+    if path.is_dir() {
+        path.push("model.bin");
+    }
+    let model = ModelBin::open(&path)?;
+    let mut reader = BufReader::new(File::open(&path).map_err(|e| e.to_string())?);
+    println!("binary version: {}", model.binary_version);
+    if model.binary_version >= 2 {
+        println!("spec: {} (revision {})", model.spec, model.spec_revision);
+    }
+    println!(
+        "variables: {}, {:.1} MiB",
+        model.variables.len(),
+        model.total_bytes() as f64 / (1024.0 * 1024.0)
+    );
+    for variable in &model.variables {
+        let mut line = format!(
+            "  {} {} {:?} {} bytes",
+            variable.name, variable.dtype, variable.shape, variable.num_bytes
+        );
+        let quantized = matches!(variable.dtype, DataType::Int8 | DataType::Int16);
+        if let Some(scale) = model.scale(&variable.name).filter(|_| quantized) {
+            let scales = model_bin::read_floats(&mut reader, scale)?;
+            let min = scales.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = scales.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            line.push_str(&format!(", scales {} to {}", min, max));
+        }
+        println!("{}", line);
+    }
+    if !model.aliases.is_empty() {
+        println!("aliases:");
+        for (alias, name) in &model.aliases {
+            println!("  {} -> {}", alias, name);
+        }
+    }
+    if model.trailing_bytes > 0 {
+        println!("trailing bytes: {}", model.trailing_bytes);
+    }
+    Ok(())
+}
+
+fn evaluate(
+    model: &str,
+    source: &str,
+    reference: &str,
+    baseline: Option<&str>,
+) -> Result<(), String> {
+    let read = |path: &str| {
+        fs::read_to_string(path)
+            .map(|v| v.lines().map(|l| l.to_string()).collect::<Vec<_>>())
+            .map_err(|e| format!("{}: {}", path, e))
+    };
+    let source = read(source)?;
+    let references = read(reference)?;
+    let translate = |model: &str| {
+        let mut translator = CTranslator::new(PathBuf::from(model), false, false)?;
+        let tokenizer = (split_tokens, join_tokens);
+        eval::translate_lines(
+            &mut translator,
+            &tokenizer,
+            &source,
+            Some(32),
+            None,
+            BatchType::Example,
+        )
+    };
+    let hypotheses = translate(model)?;
+    print_scores(model, &eval::evaluate(&hypotheses, &references)?);
+    if let Some(baseline) = baseline {
+        let baseline_hypotheses = translate(baseline)?;
+        print_scores(
+            baseline,
+            &eval::evaluate(&baseline_hypotheses, &references)?,
+        );
+        for metric in [Metric::Bleu, Metric::ChrF, Metric::Ter] {
+            let result = eval::paired_bootstrap(
+                metric,
+                &references,
+                &baseline_hypotheses,
+                &hypotheses,
+                BOOTSTRAP_SAMPLES,
+                0,
+            )?;
+            println!(
+                "{}: p = {:.4}, 95% CI {:.2} to {:.2}",
+                metric, result.p_value, result.interval.0, result.interval.1
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_scores(model: &str, scores: &Scores) {
+    println!(
+        "{}: BLEU {:.2}, chrF2++ {:.2}, TER {:.2}",
+        model, scores.bleu, scores.chrf, scores.ter
+    );
+}
+
+fn split_tokens(text: &str) -> Vec<String> {
+    text.split_whitespace().map(|v| v.to_string()).collect()
+}
+
+fn join_tokens(tokens: &[String]) -> String {
+    WhitespaceTokenizer.decode(tokens)
+}
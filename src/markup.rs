@@ -0,0 +1,468 @@
+//! Markup aware translation.
+//!
+//! Inline tags (`<b>`, `<a href="...">`, `<br/>`) and placeholders (`{0}`, `{name}`, `%s`) are taken
+//! out of the text, the plain text is translated and the tags are put back next to the target
+//! words aligned with the words they were attached to in the source.
+
+use std::collections::HashMap;
+
+use crate::alignment::{align_words, AlignmentMethod, WordAlignment};
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+const VOID_ELEMENTS: [&str; 6] = ["br", "hr", "img", "input", "meta", "wbr"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagKind {
+    Open(String),
+    Close(String),
+    SelfClosing,
+    Placeholder,
+}
+
+/// Whether a tag goes in front of or behind the word it is attached to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Side {
+    Before,
+    After,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    /// The tag as written in the source, e.g. `<a href="x">`.
+    pub text: String,
+    pub kind: TagKind,
+    /// Index of the word of the plain text the tag is attached to.
+    pub word: usize,
+    pub side: Side,
+    pub space_before: bool,
+    pub space_after: bool,
+}
+
+/// Text split into plain words and the tags attached to them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Markup {
+    pub words: Vec<String>,
+    pub tags: Vec<Tag>,
+}
+
+impl Markup {
+    /// Plain text to translate, words separated by single spaces.
+    pub fn plain(&self) -> String {
+        self.words.join(" ")
+    }
+
+    /// Puts the tags back into a list of words, using the given position for every tag.
+    pub fn render(&self, words: &[String], positions: &[(usize, Side)]) -> String {
+        let mut out = String::new();
+        let mut rendered = 0;
+        for (j, word) in words.iter().enumerate() {
+            if j > 0 {
+                out.push(' ');
+            }
+            for tag in self.tags_at(positions, j, Side::Before) {
+                out.push_str(&tag.text);
+                if tag.space_after {
+                    out.push(' ');
+                }
+                rendered += 1;
+            }
+            out.push_str(word);
+            for tag in self.tags_at(positions, j, Side::After) {
+                if tag.space_before {
+                    out.push(' ');
+                }
+                out.push_str(&tag.text);
+                rendered += 1;
+            }
+        }
+        if rendered < self.tags.len() {
+            // No words to attach to: keep the tags in source order.
+            for (i, tag) in self.tags.iter().enumerate() {
+                if positions[i].0 >= words.len() {
+                    if tag.space_before && !out.is_empty() {
+                        out.push(' ');
+                    }
+                    out.push_str(&tag.text);
+                }
+            }
+        }
+        out
+    }
+
+    fn tags_at<'a>(
+        &'a self,
+        positions: &'a [(usize, Side)],
+        word: usize,
+        side: Side,
+    ) -> impl Iterator<Item = &'a Tag> {
+        self.tags
+            .iter()
+            .zip(positions)
+            .filter(move |(_, p)| **p == (word, side))
+            .map(|(tag, _)| tag)
+    }
+}
+
+/// Splits text into plain words and tags.
+pub fn parse(text: &str) -> Markup {
+    let mut plain = String::new();
+    // (tag text, kind, byte offset in plain, space before, space after)
+    let mut found = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some((len, kind)) = match_tag(rest) {
+            let space_before = plain.ends_with(char::is_whitespace);
+            let space_after = rest[len..].starts_with(char::is_whitespace);
+            found.push((
+                rest[..len].to_string(),
+                kind,
+                plain.len(),
+                space_before,
+                space_after,
+            ));
+            rest = &rest[len..];
+        } else {
+            plain.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    let mut spans = vec![];
+    let mut offset = 0;
+    for word in plain.split_whitespace() {
+        let start = offset + plain[offset..].find(word).unwrap_or(0);
+        offset = start + word.len();
+        spans.push((start, offset));
+    }
+
+    let tags = found
+        .into_iter()
+        .map(|(text, kind, at, space_before, space_after)| {
+            let (word, side) = match kind {
+                TagKind::Close(_) => match spans.iter().rposition(|(start, _)| *start < at) {
+                    Some(word) => (word, Side::After),
+                    None => (0, Side::Before),
+                },
+                _ => match spans.iter().position(|(_, end)| *end > at) {
+                    Some(word) => (word, Side::Before),
+                    None => (spans.len().saturating_sub(1), Side::After),
+                },
+            };
+            Tag {
+                text,
+                kind,
+                word,
+                side,
+                space_before,
+                space_after,
+            }
+        })
+        .collect();
+    Markup {
+        words: plain.split_whitespace().map(|v| v.to_string()).collect(),
+        tags,
+    }
+}
+
+/// Placeholders (`{0}`, `{name}`, `%s`, `%1$d`) contained in a text, in order.
+pub fn placeholders(text: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match match_tag(rest) {
+            Some((len, TagKind::Placeholder)) => {
+                res.push(rest[..len].to_string());
+                rest = &rest[len..];
+            }
+            Some((len, _)) => rest = &rest[len..],
+            None => rest = &rest[c.len_utf8()..],
+        }
+    }
+    res
+}
+
+/// Length and kind of the tag or placeholder at the start of `text`, if any.
+fn match_tag(text: &str) -> Option<(usize, TagKind)> {
+    let bytes = text.as_bytes();
+    match bytes.first()? {
+        b'<' => {
+            let end = text.find('>')?;
+            let inner = &text[1..end];
+            if inner.contains('<') {
+                return None;
+            }
+            let (closing, body) = match inner.strip_prefix('/') {
+                Some(body) => (true, body),
+                None => (false, inner),
+            };
+            if !body.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                return None;
+            }
+            let name: String = body
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == ':')
+                .collect::<String>()
+                .to_ascii_lowercase();
+            let kind = if closing {
+                TagKind::Close(name)
+            } else if inner.ends_with('/') || VOID_ELEMENTS.contains(&name.as_str()) {
+                TagKind::SelfClosing
+            } else {
+                TagKind::Open(name)
+            };
+            Some((end + 1, kind))
+        }
+        b'{' => {
+            let end = text.find('}')?;
+            let inner = &text[1..end];
+            if inner.is_empty() || inner.contains(|c: char| c.is_whitespace() || c == '{') {
+                return None;
+            }
+            Some((end + 1, TagKind::Placeholder))
+        }
+        b'%' => {
+            let digits = bytes[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let mut len = 1 + digits;
+            if digits > 0 {
+                if bytes.get(len) != Some(&b'$') {
+                    return None;
+                }
+                len += 1;
+            }
+            match bytes.get(len)? {
+                b's' | b'd' | b'i' | b'f' | b'u' | b'x' | b'X' | b'c' | b'@' => {
+                    Some((len + 1, TagKind::Placeholder))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Output of [`translate_markup`] for one example.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarkupTranslation {
+    pub text: String,
+    /// Tags and placeholders of the source that are missing from the output.
+    pub missing: Vec<String>,
+    /// Tags and placeholders in the output that are not in the source.
+    pub unexpected: Vec<String>,
+    /// False if the tags were placed by the position heuristic instead of the attention alignment.
+    pub aligned: bool,
+}
+
+impl MarkupTranslation {
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Translates texts containing inline tags and placeholders.
+///
+/// `return_attention` is forced on; when the attention cannot be matched with the words the
+/// tags are placed proportionally to their source position.
+pub fn translate_markup<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    input: &[String],
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<Vec<MarkupTranslation>, String> {
+    let markups: Vec<Markup> = input.iter().map(|v| parse(v)).collect();
+    let tokens: Vec<Vec<String>> = markups
+        .iter()
+        .map(|m| tokenizer.encode(&m.plain()))
+        .collect();
+    let options = TranslationOptions {
+        return_attention: true,
+        ..options.unwrap_or_default()
+    };
+    let results = translator.translate_batch_results(
+        tokens.clone(),
+        max_batch_size,
+        Some(options),
+        batch_type,
+    )?;
+    if results.len() != input.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+
+    Ok(markups
+        .iter()
+        .zip(&tokens)
+        .zip(&results)
+        .zip(input)
+        .map(|(((markup, source), result), text)| {
+            let target = result.output();
+            let words: Vec<String> = tokenizer
+                .decode(target)
+                .split_whitespace()
+                .map(|v| v.to_string())
+                .collect();
+            let alignment = result
+                .attention
+                .first()
+                .map(|attention| align_words(source, target, attention, AlignmentMethod::Argmax))
+                .filter(|a| {
+                    a.source_words.len() == markup.words.len()
+                        && a.target_words.len() == words.len()
+                });
+            let positions = place_tags(markup, words.len(), alignment.as_ref());
+            let output = markup.render(&words, &positions);
+            let (missing, unexpected) = compare_tags(text, &output);
+            MarkupTranslation {
+                text: output,
+                missing,
+                unexpected,
+                aligned: alignment.is_some(),
+            }
+        })
+        .collect())
+}
+
+/// Target position of every tag of `markup`.
+fn place_tags(
+    markup: &Markup,
+    target_length: usize,
+    alignment: Option<&WordAlignment>,
+) -> Vec<(usize, Side)> {
+    let source_length = markup.words.len();
+    let proportional = |word: usize| {
+        (word * target_length)
+            .checked_div(source_length)
+            .map_or(0, |v| v.min(target_length.saturating_sub(1)))
+    };
+    let aligned = |word: usize, side: Side| -> Option<usize> {
+        let targets = alignment?.target_for(word);
+        match side {
+            Side::Before => targets.into_iter().min(),
+            Side::After => targets.into_iter().max(),
+        }
+    };
+
+    let mut positions: Vec<(usize, Side)> = markup
+        .tags
+        .iter()
+        .map(|tag| {
+            // Unaligned words borrow the position of the closest aligned word in reading direction.
+            let found = match tag.side {
+                Side::Before => (tag.word..source_length)
+                    .find_map(|w| aligned(w, Side::Before).map(|t| (t, Side::Before))),
+                Side::After => (0..=tag.word)
+                    .rev()
+                    .find_map(|w| aligned(w, Side::After).map(|t| (t, Side::After))),
+            };
+            found.unwrap_or((proportional(tag.word), tag.side))
+        })
+        .collect();
+
+    // Keep every element open before its close.
+    let mut open: Vec<(usize, &str)> = vec![];
+    for (i, tag) in markup.tags.iter().enumerate() {
+        match &tag.kind {
+            TagKind::Open(name) => open.push((i, name)),
+            TagKind::Close(name) => {
+                if let Some(k) = open.iter().rposition(|(_, n)| n == name) {
+                    let (o, _) = open.remove(k);
+                    if positions[o].0 > positions[i].0 {
+                        let (start, end) = (positions[i].0, positions[o].0);
+                        positions[o] = (start, Side::Before);
+                        positions[i] = (end, Side::After);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    positions
+}
+
+/// Tags of `source` missing from `output`, and tags of `output` not in `source`.
+fn compare_tags(source: &str, output: &str) -> (Vec<String>, Vec<String>) {
+    let count = |text: &str| {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for tag in parse(text).tags {
+            *counts.entry(tag.text).or_default() += 1;
+        }
+        counts
+    };
+    let (source, output) = (count(source), count(output));
+    let diff = |a: &HashMap<String, usize>, b: &HashMap<String, usize>| {
+        let mut res: Vec<String> = a
+            .iter()
+            .filter(|(tag, n)| b.get(*tag).copied().unwrap_or(0) < **n)
+            .map(|(tag, _)| tag.clone())
+            .collect();
+        res.sort();
+        res
+    };
+    (diff(&source, &output), diff(&output, &source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn parses_tags_and_placeholders() {
+        let markup = parse(r#"Click <a href="/x">here</a> for {0} items<br/>"#);
+        assert_eq!(markup.plain(), "Click here for items");
+        let tags: Vec<_> = markup
+            .tags
+            .iter()
+            .map(|t| (t.text.as_str(), t.word, t.side))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                (r#"<a href="/x">"#, 1, Side::Before),
+                ("</a>", 1, Side::After),
+                ("{0}", 3, Side::Before),
+                ("<br/>", 3, Side::After),
+            ]
+        );
+        assert_eq!(
+            placeholders("%s has %1$d of {n}, 100% <b>"),
+            vec!["%s", "%1$d", "{n}"]
+        );
+    }
+
+    #[test]
+    fn restores_tags_after_translation() {
+        let mut translator = MockTranslator::upper();
+        let input = vec!["Click <b>here</b> to see {0} items".to_string()];
+        let output = translate_markup(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &input,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(output[0].text, "CLICK <b>HERE</b> TO SEE {0} ITEMS");
+        assert!(output[0].aligned && output[0].is_valid());
+        assert_eq!(translator.batches[0][0].len(), 5);
+    }
+
+    #[test]
+    fn follows_reordering() {
+        let mut translator = MockTranslator::new(|v| v.iter().rev().cloned().collect());
+        let input = vec!["<b>bold</b> and <i>italic</i>".to_string()];
+        let output = translate_markup(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &input,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(output[0].text, "<i>italic</i> and <b>bold</b>");
+    }
+}
@@ -0,0 +1,296 @@
+//! Translation memory: previously approved translations are reused before calling the model.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::metrics::word_similarity;
+use crate::tokenizer::Tokenizer;
+use crate::xml;
+use crate::{BatchType, Translate, TranslationOptions};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TmEntry {
+    pub source: String,
+    pub target: String,
+}
+
+/// Where a translation of [`TranslationMemory::translate`] comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TmOrigin {
+    Exact,
+    /// Fuzzy match with the given word similarity.
+    Fuzzy(f32),
+    Model,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TmMatch {
+    pub entry: TmEntry,
+    pub origin: TmOrigin,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TmTranslation {
+    pub text: String,
+    pub origin: TmOrigin,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TranslationMemory {
+    pub source_lang: String,
+    pub target_lang: String,
+    /// Minimum word similarity for a fuzzy match to be reused (`None` only reuses exact matches).
+    pub fuzzy_threshold: Option<f32>,
+    /// Store the model translations in the memory.
+    pub write_back: bool,
+    entries: Vec<TmEntry>,
+    exact: HashMap<String, usize>,
+}
+
+impl TranslationMemory {
+    pub fn new(source_lang: &str, target_lang: &str) -> Self {
+        Self {
+            source_lang: source_lang.to_string(),
+            target_lang: target_lang.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn entries(&self) -> &[TmEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds a translation, replacing the translation of an identical source.
+    pub fn insert(&mut self, source: &str, target: &str) {
+        let entry = TmEntry {
+            source: source.trim().to_string(),
+            target: target.to_string(),
+        };
+        match self.exact.get(&normalize(source)) {
+            Some(&index) => self.entries[index] = entry,
+            None => {
+                self.exact.insert(normalize(source), self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+    }
+
+    /// Translation of a source identical up to whitespace.
+    pub fn lookup_exact(&self, source: &str) -> Option<&TmEntry> {
+        self.exact
+            .get(&normalize(source))
+            .map(|&index| &self.entries[index])
+    }
+
+    /// Most similar entry with a word similarity of at least `threshold`.
+    pub fn lookup_fuzzy(&self, source: &str, threshold: f32) -> Option<TmMatch> {
+        let length = source.split_whitespace().count() as f32;
+        let mut best: Option<(f32, &TmEntry)> = None;
+        for entry in &self.entries {
+            // The edit distance is at least the length difference.
+            let other = entry.source.split_whitespace().count() as f32;
+            if length.min(other) < threshold * length.max(other) {
+                continue;
+            }
+            let similarity = word_similarity(source, &entry.source);
+            if similarity >= threshold && best.is_none_or(|(b, _)| similarity > b) {
+                best = Some((similarity, entry));
+            }
+        }
+        best.map(|(similarity, entry)| TmMatch {
+            entry: entry.clone(),
+            origin: TmOrigin::Fuzzy(similarity),
+        })
+    }
+
+    /// Exact match, or fuzzy match if a threshold is set.
+    pub fn lookup(&self, source: &str) -> Option<TmMatch> {
+        if let Some(entry) = self.lookup_exact(source) {
+            return Some(TmMatch {
+                entry: entry.clone(),
+                origin: TmOrigin::Exact,
+            });
+        }
+        self.lookup_fuzzy(source, self.fuzzy_threshold?)
+    }
+
+    /// Translates texts, sending only the memory misses to the model in one batch.
+    pub fn translate<T: Translate, K: Tokenizer>(
+        &mut self,
+        translator: &mut T,
+        tokenizer: &K,
+        input: &[String],
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<TmTranslation>, String> {
+        let mut res: Vec<Option<TmTranslation>> = input
+            .iter()
+            .map(|source| {
+                self.lookup(source).map(|m| TmTranslation {
+                    text: m.entry.target,
+                    origin: m.origin,
+                })
+            })
+            .collect();
+        let misses: Vec<usize> = (0..input.len()).filter(|&i| res[i].is_none()).collect();
+        if !misses.is_empty() {
+            let batch = misses
+                .iter()
+                .map(|&i| tokenizer.encode(&input[i]))
+                .collect();
+            let output = translator.translate_batch(batch, max_batch_size, options, batch_type)?;
+            if output.len() != misses.len() {
+                return Err("translator returned a wrong number of results".to_string());
+            }
+            for (i, tokens) in misses.into_iter().zip(output) {
+                let text = tokenizer.decode(&tokens);
+                if self.write_back {
+                    self.insert(&input[i], &text);
+                }
+                res[i] = Some(TmTranslation {
+                    text,
+                    origin: TmOrigin::Model,
+                });
+            }
+        }
+        Ok(res.into_iter().flatten().collect())
+    }
+
+    /// Loads the translation units having a segment in both languages of the memory.
+    pub fn from_tmx_str(text: &str, source_lang: &str, target_lang: &str) -> Result<Self, String> {
+        if !text.contains("<tmx") {
+            return Err("not a TMX document".to_string());
+        }
+        let mut memory = Self::new(source_lang, target_lang);
+        for tu in xml::elements(text, "tu") {
+            let seg = |lang: &str| {
+                tu.children("tuv")
+                    .into_iter()
+                    .find(|tuv| {
+                        tuv.attribute("xml:lang")
+                            .or_else(|| tuv.attribute("lang"))
+                            .is_some_and(|v| xml::same_language(&v, lang))
+                    })
+                    .and_then(|tuv| tuv.child("seg"))
+                    .map(|seg| seg.text())
+            };
+            if let (Some(source), Some(target)) = (seg(source_lang), seg(target_lang)) {
+                memory.insert(&source, &target);
+            }
+        }
+        Ok(memory)
+    }
+
+    pub fn from_tmx(
+        path: impl AsRef<Path>,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::from_tmx_str(&text, source_lang, target_lang)
+    }
+
+    /// TMX 1.4 document with all entries.
+    pub fn to_tmx(&self) -> String {
+        let mut out =
+            String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tmx version=\"1.4\">\n");
+        out.push_str(&format!(
+            "  <header creationtool=\"{}\" creationtoolversion=\"{}\" segtype=\"sentence\" o-tmf=\"{}\" adminlang=\"en\" srclang=\"{}\" datatype=\"plaintext\"/>\n  <body>\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_NAME"),
+            xml::escape(&self.source_lang),
+        ));
+        for entry in &self.entries {
+            out.push_str("    <tu>\n");
+            for (lang, seg) in [
+                (&self.source_lang, &entry.source),
+                (&self.target_lang, &entry.target),
+            ] {
+                out.push_str(&format!(
+                    "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n",
+                    xml::escape(lang),
+                    xml::escape(seg)
+                ));
+            }
+            out.push_str("    </tu>\n");
+        }
+        out.push_str("  </body>\n</tmx>\n");
+        out
+    }
+
+    pub fn save_tmx(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        fs::write(path, self.to_tmx()).map_err(|e| e.to_string())
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn looks_up_exact_and_fuzzy() {
+        let mut memory = TranslationMemory::new("en", "de");
+        memory.insert("Save the file", "Datei speichern");
+        memory.insert("Open  the file ", "Datei öffnen");
+        assert_eq!(
+            memory.lookup_exact("Open the file").unwrap().target,
+            "Datei öffnen"
+        );
+        assert!(memory.lookup("Save the files").is_none());
+        let fuzzy = memory.lookup_fuzzy("Save the files", 0.6).unwrap();
+        assert_eq!(fuzzy.entry.target, "Datei speichern");
+        assert!(matches!(fuzzy.origin, TmOrigin::Fuzzy(v) if (v - 2.0 / 3.0).abs() < 1e-6));
+        assert!(memory.lookup_fuzzy("Close the window", 0.6).is_none());
+    }
+
+    #[test]
+    fn translates_misses_and_round_trips_tmx() {
+        let mut memory = TranslationMemory::new("en", "de");
+        memory.write_back = true;
+        memory.insert("Hello world", "Hallo Welt");
+        let mut translator = MockTranslator::upper();
+        let input = vec![
+            "Hello world".to_string(),
+            "a & b".to_string(),
+            "Hello world".to_string(),
+        ];
+        let output = memory
+            .translate(
+                &mut translator,
+                &WhitespaceTokenizer,
+                &input,
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        let origins: Vec<_> = output.iter().map(|v| v.origin).collect();
+        assert_eq!(
+            origins,
+            vec![TmOrigin::Exact, TmOrigin::Model, TmOrigin::Exact]
+        );
+        assert_eq!(output[1].text, "A & B");
+        assert_eq!(translator.translated(), 1);
+
+        let tmx = memory.to_tmx();
+        let loaded = TranslationMemory::from_tmx_str(&tmx, "en-US", "de").unwrap();
+        assert_eq!(loaded.entries(), memory.entries());
+    }
+}
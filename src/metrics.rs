@@ -0,0 +1,126 @@
+//! Text similarity measures.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Levenshtein distance between two sequences.
+pub fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Word level similarity in `[0, 1]`: one minus the edit distance divided by the longer length.
+pub fn word_similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<&str> = a.split_whitespace().collect();
+    let b: Vec<&str> = b.split_whitespace().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - edit_distance(&a, &b) as f32 / longest as f32
+}
+
+/// Sentence level chrF in `[0, 1]`: F-score (beta 2) of the character 1..6-grams, ignoring
+/// whitespace, averaged over the n-gram orders.
+pub fn chrf(hypothesis: &str, reference: &str) -> f32 {
+    let hypothesis: Vec<char> = hypothesis.chars().filter(|c| !c.is_whitespace()).collect();
+    let reference: Vec<char> = reference.chars().filter(|c| !c.is_whitespace()).collect();
+    let (mut precision, mut recall, mut orders) = (0.0, 0.0, 0);
+    for n in 1..=6 {
+        let (matches, hyp_total, ref_total) = ngram_matches(&hypothesis, &reference, n);
+        if hyp_total == 0 || ref_total == 0 {
+            continue;
+        }
+        precision += matches as f32 / hyp_total as f32;
+        recall += matches as f32 / ref_total as f32;
+        orders += 1;
+    }
+    if orders == 0 {
+        return f32::from(hypothesis == reference);
+    }
+    f_score(precision / orders as f32, recall / orders as f32, 2.0)
+}
+
+/// Bag of words F1 of the whitespace separated, lower-cased words in `[0, 1]`.
+pub fn token_overlap(a: &str, b: &str) -> f32 {
+    let a: Vec<String> = a.split_whitespace().map(|v| v.to_lowercase()).collect();
+    let b: Vec<String> = b.split_whitespace().map(|v| v.to_lowercase()).collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let (matches, a_total, b_total) = ngram_matches(&a, &b, 1);
+    if matches == 0 {
+        return 0.0;
+    }
+    f_score(
+        matches as f32 / a_total as f32,
+        matches as f32 / b_total as f32,
+        1.0,
+    )
+}
+
+/// `(clipped matches, hypothesis n-grams, reference n-grams)`.
+pub(crate) fn ngram_matches<T: Eq + Hash>(
+    hypothesis: &[T],
+    reference: &[T],
+    n: usize,
+) -> (usize, usize, usize) {
+    fn counts<T: Eq + Hash>(v: &[T], n: usize) -> HashMap<&[T], usize> {
+        let mut res = HashMap::new();
+        for ngram in v.windows(n) {
+            *res.entry(ngram).or_default() += 1;
+        }
+        res
+    }
+    let reference = counts(reference, n);
+    let hypothesis = counts(hypothesis, n);
+    let matches = hypothesis
+        .iter()
+        .map(|(k, v)| (*v).min(reference.get(k).copied().unwrap_or(0)))
+        .sum();
+    (matches, hypothesis.values().sum(), reference.values().sum())
+}
+
+pub(crate) fn f_score(precision: f32, recall: f32, beta: f32) -> f32 {
+    let beta2 = beta * beta;
+    let denominator = beta2 * precision + recall;
+    if denominator == 0.0 {
+        return 0.0;
+    }
+    (1.0 + beta2) * precision * recall / denominator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance(b"kitten", b"sitting"), 3);
+        assert_eq!(edit_distance::<u8>(b"", b"abc"), 3);
+        assert_eq!(
+            word_similarity("the red car", "the blue car"),
+            1.0 - 1.0 / 3.0
+        );
+        assert_eq!(word_similarity("", ""), 1.0);
+    }
+
+    #[test]
+    fn chrf_and_overlap() {
+        assert_eq!(chrf("the cat", "the cat"), 1.0);
+        assert_eq!(chrf("abc", "xyz"), 0.0);
+        let partial = chrf("the cat sat", "the cat sat down");
+        assert!(partial > 0.5 && partial < 1.0);
+        assert_eq!(token_overlap("The cat", "the dog"), 0.5);
+        assert_eq!(token_overlap("", ""), 1.0);
+    }
+}
@@ -0,0 +1,541 @@
+//! Reader and writer of the CTranslate2 `model.bin` format, to inspect and rewrite models without
+//! loading them.
+//!
+//! Layout (little endian, strings are u16 length prefixed and null terminated):
+//! binary version (u32), spec name and revision (u32, since version 2), variables (u32 count, then
+//! name, rank (u8), dimensions (u32), data type (u8 since version 4, item size before), size and
+//! data), aliases (u32 count, then alias and variable name, since version 3).
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Newest binary version this module and CTranslate2 3.x read.
+pub const MAX_BINARY_VERSION: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType {
+    Float32,
+    Int8,
+    Int16,
+    Int32,
+    Float16,
+    BFloat16,
+}
+
+impl DataType {
+    pub fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => DataType::Float32,
+            1 => DataType::Int8,
+            2 => DataType::Int16,
+            3 => DataType::Int32,
+            4 => DataType::Float16,
+            5 => DataType::BFloat16,
+            _ => return None,
+        })
+    }
+
+    pub fn id(self) -> u8 {
+        match self {
+            DataType::Float32 => 0,
+            DataType::Int8 => 1,
+            DataType::Int16 => 2,
+            DataType::Int32 => 3,
+            DataType::Float16 => 4,
+            DataType::BFloat16 => 5,
+        }
+    }
+
+    /// Binary versions before 4 only store the item size.
+    fn from_item_size(size: u8) -> Option<Self> {
+        Some(match size {
+            1 => DataType::Int8,
+            2 => DataType::Int16,
+            4 => DataType::Float32,
+            _ => return None,
+        })
+    }
+
+    pub fn item_size(self) -> u64 {
+        match self {
+            DataType::Int8 => 1,
+            DataType::Int16 | DataType::Float16 | DataType::BFloat16 => 2,
+            DataType::Float32 | DataType::Int32 => 4,
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DataType::Float32 => "float32",
+            DataType::Int8 => "int8",
+            DataType::Int16 => "int16",
+            DataType::Int32 => "int32",
+            DataType::Float16 => "float16",
+            DataType::BFloat16 => "bfloat16",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub shape: Vec<u32>,
+    pub dtype: DataType,
+    /// Position of the data in the file.
+    pub offset: u64,
+    pub num_bytes: u64,
+}
+
+impl Variable {
+    pub fn num_elements(&self) -> u64 {
+        self.shape.iter().map(|&v| v as u64).product()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelBin {
+    pub binary_version: u32,
+    /// Model specification, e.g. `TransformerSpec`; empty in version 1.
+    pub spec: String,
+    /// Revision of the specification, 1 before version 2.
+    pub spec_revision: u32,
+    pub variables: Vec<Variable>,
+    /// (alias, variable name) pairs, e.g. shared embeddings.
+    pub aliases: Vec<(String, String)>,
+    /// Bytes after the aliases, ignored by CTranslate2.
+    pub trailing_bytes: u64,
+}
+
+impl ModelBin {
+    /// Reads the header of every variable, skipping over the data.
+    pub fn parse<R: Read + Seek>(reader: &mut R) -> Result<Self, String> {
+        let len = reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        let binary_version = read_u32(reader)?;
+        if binary_version == 0 || binary_version > MAX_BINARY_VERSION {
+            return Err(format!(
+                "unsupported binary version {} (supported: 1 to {})",
+                binary_version, MAX_BINARY_VERSION
+            ));
+        }
+        let (spec, spec_revision) = if binary_version >= 2 {
+            (read_string(reader)?, read_u32(reader)?)
+        } else {
+            (String::new(), 1)
+        };
+
+        let count = read_u32(reader)?;
+        let mut variables = vec![];
+        for _ in 0..count {
+            let name = read_string(reader)?;
+            let rank = read_u8(reader)?;
+            let shape = (0..rank)
+                .map(|_| read_u32(reader))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (dtype, num_bytes) = if binary_version >= 4 {
+                let id = read_u8(reader)?;
+                let dtype = DataType::from_id(id)
+                    .ok_or_else(|| format!("{}: unknown data type {}", name, id))?;
+                (dtype, read_u32(reader)? as u64)
+            } else {
+                let size = read_u8(reader)?;
+                let dtype = DataType::from_item_size(size)
+                    .ok_or_else(|| format!("{}: unknown item size {}", name, size))?;
+                (dtype, read_u32(reader)? as u64 * size as u64)
+            };
+            let offset = reader.stream_position().map_err(|e| e.to_string())?;
+            let variable = Variable {
+                name,
+                shape,
+                dtype,
+                offset,
+                num_bytes,
+            };
+            if variable.num_elements() * dtype.item_size() != num_bytes {
+                return Err(format!(
+                    "{}: shape {:?} does not match its size of {} bytes",
+                    variable.name, variable.shape, num_bytes
+                ));
+            }
+            if offset + num_bytes > len {
+                return Err(format!("{}: truncated file", variable.name));
+            }
+            reader
+                .seek(SeekFrom::Current(num_bytes as i64))
+                .map_err(|e| e.to_string())?;
+            variables.push(variable);
+        }
+
+        let mut aliases = vec![];
+        if binary_version >= 3 {
+            for _ in 0..read_u32(reader)? {
+                let alias = read_string(reader)?;
+                let name = read_string(reader)?;
+                if !variables.iter().any(|v| v.name == name) {
+                    return Err(format!("alias {} of unknown variable {}", alias, name));
+                }
+                aliases.push((alias, name));
+            }
+        }
+        let end = reader.stream_position().map_err(|e| e.to_string())?;
+        Ok(Self {
+            binary_version,
+            spec,
+            spec_revision,
+            variables,
+            aliases,
+            trailing_bytes: len - end,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        Self::parse(&mut BufReader::new(file))
+    }
+
+    /// Variable by name or alias.
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        let name = self
+            .aliases
+            .iter()
+            .find(|(alias, _)| alias == name)
+            .map_or(name, |(_, v)| v.as_str());
+        self.variables.iter().find(|v| v.name == name)
+    }
+
+    /// Quantization scales of an int8 or int16 variable (`<name>_scale`).
+    pub fn scale(&self, name: &str) -> Option<&Variable> {
+        let name = &self.variable(name)?.name;
+        self.variable(&format!("{}_scale", name))
+    }
+
+    /// Source embeddings: `encoder/embeddings_0/weight` since source features were added,
+    /// `encoder/embeddings/weight` in older revisions.
+    pub fn source_embeddings(&self) -> Option<&Variable> {
+        self.variable("encoder/embeddings_0/weight")
+            .or_else(|| self.variable("encoder/embeddings/weight"))
+    }
+
+    pub fn target_embeddings(&self) -> Option<&Variable> {
+        self.variable("decoder/embeddings/weight")
+    }
+
+    /// Size of the variable data.
+    pub fn total_bytes(&self) -> u64 {
+        self.variables.iter().map(|v| v.num_bytes).sum()
+    }
+}
+
+/// Raw data of a variable.
+pub fn read_data<R: Read + Seek>(reader: &mut R, variable: &Variable) -> Result<Vec<u8>, String> {
+    reader
+        .seek(SeekFrom::Start(variable.offset))
+        .map_err(|e| e.to_string())?;
+    let mut buf = vec![0; variable.num_bytes as usize];
+    reader.read_exact(&mut buf).map_err(io_error)?;
+    Ok(buf)
+}
+
+/// Data of a floating point variable as `f32`.
+pub fn read_floats<R: Read + Seek>(
+    reader: &mut R,
+    variable: &Variable,
+) -> Result<Vec<f32>, String> {
+    let data = read_data(reader, variable)?;
+    match variable.dtype {
+        DataType::Float32 => Ok(data
+            .chunks_exact(4)
+            .map(|v| f32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+            .collect()),
+        DataType::Float16 => Ok(data
+            .chunks_exact(2)
+            .map(|v| f16_to_f32(u16::from_le_bytes([v[0], v[1]])))
+            .collect()),
+        DataType::BFloat16 => Ok(data
+            .chunks_exact(2)
+            .map(|v| f32::from_bits((u16::from_le_bytes([v[0], v[1]]) as u32) << 16))
+            .collect()),
+        dtype => Err(format!("{}: {} is not a float type", variable.name, dtype)),
+    }
+}
+
+/// Variable with its data, for [`write`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor {
+    pub name: String,
+    pub shape: Vec<u32>,
+    pub dtype: DataType,
+    pub data: Vec<u8>,
+}
+
+impl Tensor {
+    pub fn from_floats(name: &str, shape: Vec<u32>, values: &[f32]) -> Self {
+        Self {
+            name: name.to_string(),
+            shape,
+            dtype: DataType::Float32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+}
+
+/// Writes a model in a binary version from 4 on.
+pub fn write<W: Write>(
+    writer: &mut W,
+    binary_version: u32,
+    spec: &str,
+    spec_revision: u32,
+    tensors: &[Tensor],
+    aliases: &[(String, String)],
+) -> Result<(), String> {
+    if !(4..=MAX_BINARY_VERSION).contains(&binary_version) {
+        return Err(format!(
+            "cannot write binary version {} (supported: 4 to {})",
+            binary_version, MAX_BINARY_VERSION
+        ));
+    }
+    let mut out = vec![];
+    out.extend(binary_version.to_le_bytes());
+    write_string(&mut out, spec)?;
+    out.extend(spec_revision.to_le_bytes());
+    out.extend((tensors.len() as u32).to_le_bytes());
+    writer.write_all(&out).map_err(|e| e.to_string())?;
+    for tensor in tensors {
+        let elements: u64 = tensor.shape.iter().map(|&v| v as u64).product();
+        if elements * tensor.dtype.item_size() != tensor.data.len() as u64 {
+            return Err(format!(
+                "{}: shape {:?} does not match its size of {} bytes",
+                tensor.name,
+                tensor.shape,
+                tensor.data.len()
+            ));
+        }
+        let mut out = vec![];
+        write_string(&mut out, &tensor.name)?;
+        out.push(tensor.shape.len() as u8);
+        tensor
+            .shape
+            .iter()
+            .for_each(|v| out.extend(v.to_le_bytes()));
+        out.push(tensor.dtype.id());
+        out.extend((tensor.data.len() as u32).to_le_bytes());
+        writer.write_all(&out).map_err(|e| e.to_string())?;
+        writer.write_all(&tensor.data).map_err(|e| e.to_string())?;
+    }
+    let mut out = vec![];
+    out.extend((aliases.len() as u32).to_le_bytes());
+    for (alias, name) in aliases {
+        write_string(&mut out, alias)?;
+        write_string(&mut out, name)?;
+    }
+    writer.write_all(&out).map_err(|e| e.to_string())
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) -> Result<(), String> {
+    let len = u16::try_from(value.len() + 1).map_err(|_| format!("name too long: {}", value))?;
+    out.extend(len.to_le_bytes());
+    out.extend(value.as_bytes());
+    out.push(0);
+    Ok(())
+}
+
+/// Rounds to the nearest float16, ties to even.
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let round = |value: u32, shift: u32| {
+        let (rest, half) = (value & ((1 << shift) - 1), 1 << (shift - 1));
+        let value = value >> shift;
+        if rest > half || (rest == half && value & 1 == 1) {
+            value + 1
+        } else {
+            value
+        }
+    };
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        sign | 0x7c00
+    } else if e <= 0 {
+        // Subnormal: the implicit leading bit becomes explicit.
+        if e < -10 {
+            return sign;
+        }
+        sign | round(mantissa | 0x80_0000, (14 - e) as u32) as u16
+    } else {
+        // A rounding carry into the exponent is still correct, up to infinity.
+        sign | round(((e as u32) << 23) | mantissa, 13) as u16
+    }
+}
+
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let fraction = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * fraction * 2f32.powi(-24),
+        0x1f if fraction == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + fraction / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn io_error(e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => "truncated file".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8, String> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf).map_err(io_error)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf).map_err(io_error)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, String> {
+    let mut len = [0; 2];
+    reader.read_exact(&mut len).map_err(io_error)?;
+    let mut buf = vec![0; u16::from_le_bytes(len) as usize];
+    reader.read_exact(&mut buf).map_err(io_error)?;
+    if buf.last() == Some(&0) {
+        buf.pop();
+    }
+    String::from_utf8(buf).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Versions before 4, which [`write`] does not produce: variables are (name, shape, item
+    /// size, data).
+    fn legacy_fixture(version: u32, variables: &[(&str, &[u32], u8, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend(version.to_le_bytes());
+        if version >= 2 {
+            write_string(&mut out, "TransformerSpec").unwrap();
+            out.extend(3u32.to_le_bytes());
+        }
+        out.extend((variables.len() as u32).to_le_bytes());
+        for (name, shape, item_size, data) in variables {
+            write_string(&mut out, name).unwrap();
+            out.push(shape.len() as u8);
+            shape.iter().for_each(|v| out.extend(v.to_le_bytes()));
+            out.push(*item_size);
+            out.extend(((data.len() / *item_size as usize) as u32).to_le_bytes());
+            out.extend(data);
+        }
+        if version >= 3 {
+            out.extend(1u32.to_le_bytes());
+            write_string(&mut out, "decoder/embeddings/weight").unwrap();
+            write_string(&mut out, "encoder/embeddings_0/weight").unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn parses_current_version() {
+        let scales: Vec<u8> = [0.5f32, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let half: Vec<u8> = [0x3c00u16, 0xc000]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let tensor = |name: &str, shape: Vec<u32>, dtype, data| Tensor {
+            name: name.to_string(),
+            shape,
+            dtype,
+            data,
+        };
+        let mut bytes = vec![];
+        write(
+            &mut bytes,
+            6,
+            "TransformerSpec",
+            3,
+            &[
+                tensor(
+                    "encoder/embeddings_0/weight",
+                    vec![2, 3],
+                    DataType::Int8,
+                    vec![1; 6],
+                ),
+                tensor(
+                    "encoder/embeddings_0/weight_scale",
+                    vec![2],
+                    DataType::Float32,
+                    scales,
+                ),
+                tensor("decoder/projection/bias", vec![2], DataType::Float16, half),
+            ],
+            &[(
+                "decoder/embeddings/weight".to_string(),
+                "encoder/embeddings_0/weight".to_string(),
+            )],
+        )
+        .unwrap();
+        let model = ModelBin::parse(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(
+            (model.spec.as_str(), model.spec_revision),
+            ("TransformerSpec", 3)
+        );
+        assert_eq!(model.variables.len(), 3);
+        assert_eq!(model.total_bytes(), 6 + 8 + 4);
+        assert_eq!(model.trailing_bytes, 0);
+
+        let target = model.target_embeddings().unwrap();
+        assert_eq!(
+            (target.dtype, target.shape.clone()),
+            (DataType::Int8, vec![2, 3])
+        );
+        let scale = model.scale("decoder/embeddings/weight").unwrap();
+        assert_eq!(
+            read_floats(&mut Cursor::new(&bytes), scale).unwrap(),
+            [0.5, 2.0]
+        );
+        let bias = model.variable("decoder/projection/bias").unwrap();
+        assert_eq!(
+            read_floats(&mut Cursor::new(&bytes), bias).unwrap(),
+            [1.0, -2.0]
+        );
+
+        assert_eq!(
+            ModelBin::parse(&mut Cursor::new(&bytes[..bytes.len() - 12])),
+            Err("truncated file".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_old_versions() {
+        let bytes = legacy_fixture(1, &[("encoder/embeddings/weight", &[2, 2], 4, vec![0; 16])]);
+        let model = ModelBin::parse(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!((model.spec.as_str(), model.spec_revision), ("", 1));
+        let source = model.source_embeddings().unwrap();
+        assert_eq!((source.dtype, source.num_bytes), (DataType::Float32, 16));
+
+        let bytes = legacy_fixture(3, &[("encoder/embeddings_0/weight", &[4], 2, vec![0; 8])]);
+        let model = ModelBin::parse(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(model.target_embeddings().unwrap().dtype, DataType::Int16);
+        assert_eq!(model.aliases.len(), 1);
+
+        let mut bytes = bytes;
+        bytes[..4].copy_from_slice(&7u32.to_le_bytes());
+        assert!(ModelBin::parse(&mut Cursor::new(&bytes)).is_err());
+    }
+}
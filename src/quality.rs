@@ -0,0 +1,325 @@
+//! Per-sentence quality estimation, for routing low-confidence translations to human review.
+//!
+//! The confidence combines the length-normalized log-prob of the best hypothesis, its agreement
+//! with the other n-best hypotheses and attention coverage checks, each mapped to `[0, 1]`.
+
+use crate::metrics::edit_distance;
+use crate::{BatchType, Score, ScoringResult, Translate, TranslationOptions, TranslationResult};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityOptions {
+    /// Translations with a lower confidence are flagged.
+    pub threshold: f32,
+    /// Hypotheses requested for the agreement metric.
+    pub num_hypotheses: usize,
+    /// Total attention a source token needs to count as translated.
+    pub min_source_attention: f32,
+    /// Highest attention a target token needs to count as grounded in the source.
+    pub min_target_attention: f32,
+    /// Weights of the log-prob, agreement and coverage in the confidence.
+    pub weights: [f32; 3],
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            num_hypotheses: 4,
+            min_source_attention: 0.5,
+            min_target_attention: 0.3,
+            weights: [0.5, 0.25, 0.25],
+        }
+    }
+}
+
+/// Reason a translation scored below the threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualityIssue {
+    /// The model is unsure of the output.
+    LowProbability,
+    /// The n-best hypotheses disagree.
+    Disagreement,
+    /// Source tokens received little attention.
+    Untranslated,
+    /// Target tokens are not grounded in the source.
+    Hallucination,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QualityEstimate {
+    /// Tokens of the best hypothesis.
+    pub tokens: Vec<String>,
+    /// Length-normalized log-prob of the best hypothesis.
+    pub log_prob: f32,
+    /// Log-prob of each target token and the end token (empty unless scored token by token).
+    pub token_scores: Vec<f32>,
+    /// Mean similarity of the best hypothesis to the others, weighted by their scores.
+    pub agreement: Option<f32>,
+    /// Share of the source tokens that received attention.
+    pub source_coverage: Option<f32>,
+    /// Share of the target tokens grounded in the source.
+    pub target_coverage: Option<f32>,
+    /// Weighted mean of the available measures, in `[0, 1]`.
+    pub confidence: f32,
+    /// Set when the confidence or one of the measures is below the threshold.
+    pub flagged: bool,
+    /// Measures below the threshold.
+    pub issues: Vec<QualityIssue>,
+}
+
+/// Estimates the quality of one translation.
+///
+/// Uses the token log-probs of `scoring` when given, else the hypothesis score from
+/// `return_scores`, which CTranslate2 normalizes by length.
+pub fn estimate(
+    source: &[String],
+    result: &TranslationResult,
+    scoring: Option<&ScoringResult>,
+    options: &QualityOptions,
+) -> QualityEstimate {
+    let tokens = result.output().to_vec();
+    let (log_prob, token_scores) = match scoring {
+        Some(scoring) => (scoring.normalized_score(), scoring.tokens_score.clone()),
+        None => (result.score().unwrap_or(0.0), vec![]),
+    };
+    let agreement = agreement(result);
+    let attention = result.attention.first().filter(|v| !v.is_empty());
+    let (source_coverage, target_coverage) = match attention {
+        Some(attention) => {
+            let (s, t) = coverage(attention, source.len(), tokens.len(), options);
+            (Some(s), Some(t))
+        }
+        None => (None, None),
+    };
+
+    let probability = log_prob.min(0.0).exp();
+    let coverage = match (source_coverage, target_coverage) {
+        (Some(s), Some(t)) => Some(s.min(t)),
+        _ => None,
+    };
+    let [w_prob, w_agreement, w_coverage] = options.weights;
+    let measures = [
+        (Some(probability), w_prob),
+        (agreement, w_agreement),
+        (coverage, w_coverage),
+    ];
+    let total: f32 = measures
+        .iter()
+        .filter(|(v, _)| v.is_some())
+        .map(|(_, w)| w)
+        .sum();
+    let confidence = if total > 0.0 {
+        measures
+            .iter()
+            .filter_map(|(v, w)| v.map(|v| v * w))
+            .sum::<f32>()
+            / total
+    } else {
+        probability
+    };
+
+    let below = |v: Option<f32>| v.is_some_and(|v| v < options.threshold);
+    let issues = [
+        (below(Some(probability)), QualityIssue::LowProbability),
+        (below(agreement), QualityIssue::Disagreement),
+        (below(source_coverage), QualityIssue::Untranslated),
+        (below(target_coverage), QualityIssue::Hallucination),
+    ]
+    .into_iter()
+    .filter(|(b, _)| *b)
+    .map(|(_, issue)| issue)
+    .collect::<Vec<_>>();
+
+    QualityEstimate {
+        tokens,
+        log_prob,
+        token_scores,
+        agreement,
+        source_coverage,
+        target_coverage,
+        confidence,
+        flagged: confidence < options.threshold || !issues.is_empty(),
+        issues,
+    }
+}
+
+/// Translates a batch and estimates the quality of every example from the hypothesis scores.
+///
+/// `return_scores`, `return_attention` and `num_hypotheses` are forced on.
+pub fn translate_with_quality<T: Translate>(
+    translator: &mut T,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+    quality: &QualityOptions,
+) -> Result<Vec<QualityEstimate>, String> {
+    let results = translator.translate_batch_results(
+        input.clone(),
+        max_batch_size,
+        Some(quality_options(options, quality)),
+        batch_type,
+    )?;
+    if results.len() != input.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+    Ok(input
+        .iter()
+        .zip(&results)
+        .map(|(source, result)| estimate(source, result, None, quality))
+        .collect())
+}
+
+/// Like [`translate_with_quality`], but scores the best hypotheses again to use the token
+/// log-probs.
+pub fn translate_with_quality_scored<T: Translate + Score>(
+    translator: &mut T,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+    quality: &QualityOptions,
+) -> Result<Vec<QualityEstimate>, String> {
+    let results = translator.translate_batch_results(
+        input.clone(),
+        max_batch_size,
+        Some(quality_options(options, quality)),
+        batch_type,
+    )?;
+    if results.len() != input.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+    let targets = results.iter().map(|v| v.output().to_vec()).collect();
+    let scores = translator.score_batch(input.clone(), targets, max_batch_size, batch_type)?;
+    if scores.len() != input.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+    Ok(input
+        .iter()
+        .zip(&results)
+        .zip(&scores)
+        .map(|((source, result), scoring)| estimate(source, result, Some(scoring), quality))
+        .collect())
+}
+
+fn quality_options(
+    options: Option<TranslationOptions>,
+    quality: &QualityOptions,
+) -> TranslationOptions {
+    let mut options = options.unwrap_or_default();
+    options.num_hypotheses = options.num_hypotheses.max(quality.num_hypotheses);
+    // Beam search returns at most `beam_size` hypotheses, sampling any number.
+    if options.sampling_topk == 1 {
+        options.beam_size = options.beam_size.max(options.num_hypotheses);
+    }
+    options.return_scores = true;
+    options.return_attention = true;
+    options
+}
+
+/// Similarity of the best hypothesis to the others, weighted by the softmax of their scores.
+fn agreement(result: &TranslationResult) -> Option<f32> {
+    let (best, others) = result.hypotheses.split_first()?;
+    if others.is_empty() {
+        return None;
+    }
+    let scores = result.scores.get(1..).filter(|v| v.len() == others.len());
+    let weights: Vec<f32> = match scores {
+        Some(scores) => {
+            let max = scores.iter().copied().fold(f32::MIN, f32::max);
+            scores.iter().map(|v| (v - max).exp()).collect()
+        }
+        None => vec![1.0; others.len()],
+    };
+    let total: f32 = weights.iter().sum();
+    let similarity = others
+        .iter()
+        .zip(&weights)
+        .map(|(other, w)| {
+            let longest = best.len().max(other.len()).max(1);
+            w * (1.0 - edit_distance(best, other) as f32 / longest as f32)
+        })
+        .sum::<f32>();
+    Some(similarity / total)
+}
+
+/// `(source coverage, target coverage)` of an attention matrix indexed as
+/// `[target token][source token]`; special token rows and columns are ignored.
+fn coverage(
+    attention: &[Vec<f32>],
+    source_length: usize,
+    target_length: usize,
+    options: &QualityOptions,
+) -> (f32, f32) {
+    let rows: Vec<&[f32]> = attention
+        .iter()
+        .take(target_length)
+        .map(|row| &row[..row.len().min(source_length)])
+        .collect();
+    let covered = (0..source_length)
+        .filter(|&j| {
+            rows.iter().filter_map(|row| row.get(j)).sum::<f32>() >= options.min_source_attention
+        })
+        .count();
+    let grounded = rows
+        .iter()
+        .filter(|row| row.iter().any(|&w| w >= options.min_target_attention))
+        .count();
+    let ratio = |n: usize, d: usize| if d == 0 { 1.0 } else { n as f32 / d as f32 };
+    (ratio(covered, source_length), ratio(grounded, rows.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+
+    fn tokens(text: &str) -> Vec<String> {
+        text.split(' ').map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_untranslated_content() {
+        let mut translator = MockTranslator::new(|v| v[..1].to_vec());
+        let input = vec![tokens("▁hello"), tokens("▁the ▁world ▁is ▁round")];
+        let estimates = translate_with_quality_scored(
+            &mut translator,
+            input,
+            None,
+            None,
+            BatchType::Example,
+            &QualityOptions::default(),
+        )
+        .unwrap();
+        assert!(!estimates[0].flagged);
+        assert_eq!(estimates[0].agreement, Some(1.0));
+        assert_eq!(estimates[0].token_scores, vec![-0.1, -0.1]);
+        assert!((estimates[0].log_prob + 0.1).abs() < 1e-6);
+        assert_eq!(estimates[1].source_coverage, Some(0.25));
+        assert!(estimates[1].flagged);
+        assert_eq!(estimates[1].issues, vec![QualityIssue::Untranslated]);
+        assert!(estimates[1].confidence < estimates[0].confidence);
+    }
+
+    #[test]
+    fn measures_hypothesis_agreement() {
+        let options = QualityOptions::default();
+        let result = TranslationResult {
+            hypotheses: vec![tokens("a b c d"), tokens("a b c d"), tokens("w x y z")],
+            scores: vec![-0.2, -0.5, -0.5],
+            attention: vec![],
+        };
+        let quality = estimate(&tokens("a b c d"), &result, None, &options);
+        assert_eq!(quality.agreement, Some(0.5));
+        assert_eq!(quality.source_coverage, None);
+        assert!(!quality.flagged);
+
+        let result = TranslationResult {
+            scores: vec![-3.0, -3.0, -3.0],
+            ..result
+        };
+        let quality = estimate(&tokens("a b c d"), &result, None, &options);
+        assert!(quality.flagged);
+        assert_eq!(quality.issues, vec![QualityIssue::LowProbability]);
+    }
+}
@@ -0,0 +1,283 @@
+//! Conversion of a model directory to another weight type, like the `--quantization` option of the
+//! CTranslate2 converters.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::model_bin::{self, DataType, ModelBin, Tensor, Variable};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quantization {
+    /// Weights in int8 with one scale per row, other variables in float32.
+    Int8,
+    Float16,
+    Float32,
+}
+
+impl FromStr for Quantization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "int8" => Ok(Quantization::Int8),
+            "float16" => Ok(Quantization::Float16),
+            "float32" => Ok(Quantization::Float32),
+            _ => Err(format!(
+                "unknown quantization {}, expected int8, float16 or float32",
+                s
+            )),
+        }
+    }
+}
+
+/// Writes the model of `input` with its weights converted to `quantization` to `output`, and
+/// copies the other files of the directory (config, vocabularies).
+///
+/// Only the weights of linear layers and embeddings (2D variables named `weight`) are quantized
+/// to int8, as the converters do.
+pub fn quantize_model_dir(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    quantization: Quantization,
+) -> Result<(), String> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    if input == output {
+        return Err("the output directory must differ from the input directory".to_string());
+    }
+    let path = input.join("model.bin");
+    let model = ModelBin::open(&path)?;
+    if model.binary_version < 2 {
+        return Err(
+            "models before binary version 2 have no spec and are not supported".to_string(),
+        );
+    }
+    let mut reader = BufReader::new(File::open(&path).map_err(|e| e.to_string())?);
+
+    // Scales of the quantized variables of the input, replaced by the new ones.
+    let old_scales: Vec<&str> = model
+        .variables
+        .iter()
+        .filter(|v| matches!(v.dtype, DataType::Int8 | DataType::Int16))
+        .filter_map(|v| model.scale(&v.name))
+        .map(|v| v.name.as_str())
+        .collect();
+    let mut tensors = vec![];
+    for variable in &model.variables {
+        if old_scales.contains(&variable.name.as_str()) {
+            continue;
+        }
+        let Some(values) = float_values(&model, &mut reader, variable)? else {
+            tensors.push(Tensor {
+                name: variable.name.clone(),
+                shape: variable.shape.clone(),
+                dtype: variable.dtype,
+                data: model_bin::read_data(&mut reader, variable)?,
+            });
+            continue;
+        };
+        let (name, shape) = (variable.name.as_str(), variable.shape.clone());
+        match quantization {
+            Quantization::Int8 if is_quantizable(variable) => {
+                let rows = shape[0];
+                let (data, scales) = quantize_rows(&values, rows as usize);
+                tensors.push(Tensor {
+                    name: name.to_string(),
+                    shape,
+                    dtype: DataType::Int8,
+                    data,
+                });
+                tensors.push(Tensor::from_floats(
+                    &format!("{}_scale", name),
+                    vec![rows],
+                    &scales,
+                ));
+            }
+            Quantization::Float16 => tensors.push(Tensor {
+                name: name.to_string(),
+                shape,
+                dtype: DataType::Float16,
+                data: values
+                    .iter()
+                    .flat_map(|v| model_bin::f32_to_f16(*v).to_le_bytes())
+                    .collect(),
+            }),
+            _ => tensors.push(Tensor::from_floats(name, shape, &values)),
+        }
+    }
+
+    // Shared weights share their scales too.
+    let written = |name: &str| tensors.iter().any(|v| v.name == name);
+    let mut aliases = vec![];
+    for (alias, name) in &model.aliases {
+        if written(name) {
+            aliases.push((alias.clone(), name.clone()));
+        }
+        let scale = format!("{}_scale", name);
+        if written(&scale) {
+            aliases.push((format!("{}_scale", alias), scale));
+        }
+    }
+    aliases.dedup();
+
+    fs::create_dir_all(output).map_err(|e| e.to_string())?;
+    let mut writer =
+        BufWriter::new(File::create(output.join("model.bin")).map_err(|e| e.to_string())?);
+    model_bin::write(
+        &mut writer,
+        model.binary_version.max(4),
+        &model.spec,
+        model.spec_revision,
+        &tensors,
+        &aliases,
+    )?;
+    writer.flush().map_err(|e| e.to_string())?;
+
+    for entry in fs::read_dir(input).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.path().is_file() && entry.file_name() != "model.bin" {
+            fs::copy(entry.path(), output.join(entry.file_name())).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn is_quantizable(variable: &Variable) -> bool {
+    (variable.name == "weight" || variable.name.ends_with("/weight")) && variable.shape.len() == 2
+}
+
+/// Values of a float variable, or of a quantized one with its scales. `None` for other types.
+fn float_values<R: Read + Seek>(
+    model: &ModelBin,
+    reader: &mut R,
+    variable: &Variable,
+) -> Result<Option<Vec<f32>>, String> {
+    let quantized = match variable.dtype {
+        DataType::Float32 | DataType::Float16 | DataType::BFloat16 => {
+            return model_bin::read_floats(reader, variable).map(Some)
+        }
+        DataType::Int8 | DataType::Int16 => match model.scale(&variable.name) {
+            Some(scale) => scale,
+            None => return Ok(None),
+        },
+        DataType::Int32 => return Ok(None),
+    };
+    let scales = model_bin::read_floats(reader, quantized)?;
+    let data = model_bin::read_data(reader, variable)?;
+    let values: Vec<f32> = match variable.dtype {
+        DataType::Int8 => data.iter().map(|&v| v as i8 as f32).collect(),
+        _ => data
+            .chunks_exact(2)
+            .map(|v| i16::from_le_bytes([v[0], v[1]]) as f32)
+            .collect(),
+    };
+    // One scale per row (int8) or for the whole variable (int16).
+    let columns = match scales.len() {
+        0 => return Err(format!("{}: empty scales", variable.name)),
+        1 => values.len().max(1),
+        rows => values.len() / rows,
+    };
+    Ok(Some(
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v / scales[(i / columns.max(1)).min(scales.len() - 1)])
+            .collect(),
+    ))
+}
+
+/// Symmetric int8 quantization of every row: `round(value * 127 / max(|row|))`.
+fn quantize_rows(values: &[f32], rows: usize) -> (Vec<u8>, Vec<f32>) {
+    let columns = (values.len() / rows.max(1)).max(1);
+    let mut data = Vec::with_capacity(values.len());
+    let mut scales = Vec::with_capacity(rows);
+    for row in values.chunks(columns) {
+        let max = row.iter().fold(0f32, |max, v| max.max(v.abs()));
+        let scale = if max == 0.0 { 1.0 } else { 127.0 / max };
+        scales.push(scale);
+        data.extend(
+            row.iter()
+                .map(|v| (v * scale).round_ties_even().clamp(-127.0, 127.0) as i8 as u8),
+        );
+    }
+    (data, scales)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floats(model: &ModelBin, path: &Path, name: &str) -> Vec<f32> {
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        float_values(model, &mut reader, model.variable(name).unwrap())
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn converts_weight_types() {
+        let dir =
+            std::env::temp_dir().join(format!("rustyctranslate2-quantize-{}", std::process::id()));
+        let input = dir.join("float32");
+        fs::create_dir_all(&input).unwrap();
+        let weight = [0.5, -1.0, 0.25, 0.0, 0.0, 0.0];
+        let tensors = [
+            Tensor::from_floats("encoder/embeddings_0/weight", vec![2, 3], &weight),
+            Tensor::from_floats("encoder/layer_norm/gamma", vec![3], &[1.0, 0.1, 2.0]),
+        ];
+        let aliases = [(
+            "decoder/embeddings/weight".to_string(),
+            "encoder/embeddings_0/weight".to_string(),
+        )];
+        let mut file = File::create(input.join("model.bin")).unwrap();
+        model_bin::write(&mut file, 6, "TransformerSpec", 3, &tensors, &aliases).unwrap();
+        fs::write(input.join("shared_vocabulary.json"), r#"["<unk>", "a"]"#).unwrap();
+
+        let int8 = dir.join("int8");
+        quantize_model_dir(&input, &int8, Quantization::Int8).unwrap();
+        let model = ModelBin::open(int8.join("model.bin")).unwrap();
+        let embeddings = model.variable("decoder/embeddings/weight").unwrap();
+        assert_eq!(embeddings.dtype, DataType::Int8);
+        let mut reader = BufReader::new(File::open(int8.join("model.bin")).unwrap());
+        let data = model_bin::read_data(&mut reader, embeddings).unwrap();
+        assert_eq!(
+            data.iter().map(|&v| v as i8).collect::<Vec<_>>(),
+            [64, -127, 32, 0, 0, 0]
+        );
+        let scale = model.scale("decoder/embeddings/weight").unwrap();
+        assert_eq!(
+            model_bin::read_floats(&mut reader, scale).unwrap(),
+            [127.0, 1.0]
+        );
+        assert_eq!(
+            model.variable("encoder/layer_norm/gamma").unwrap().dtype,
+            DataType::Float32
+        );
+        assert!(int8.join("shared_vocabulary.json").is_file());
+
+        let float16 = dir.join("float16");
+        quantize_model_dir(&int8, &float16, Quantization::Float16).unwrap();
+        let model = ModelBin::open(float16.join("model.bin")).unwrap();
+        assert_eq!(model.variables.len(), 2);
+        assert!(model.scale("encoder/embeddings_0/weight").is_none());
+        let restored = floats(
+            &model,
+            &float16.join("model.bin"),
+            "encoder/embeddings_0/weight",
+        );
+        for (restored, original) in restored.iter().zip(weight) {
+            assert!((restored - original).abs() < 0.01);
+        }
+        let gamma = floats(
+            &model,
+            &float16.join("model.bin"),
+            "encoder/layer_norm/gamma",
+        );
+        assert_eq!(
+            gamma,
+            [1.0, model_bin::f16_to_f32(model_bin::f32_to_f16(0.1)), 2.0]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+//! Model files read from memory instead of a model directory, see [`crate::CTranslator::from_reader`].
+
+use std::collections::HashMap;
+
+/// Files CTranslate2 may read from a model, all optional except `model.bin`.
+pub const MODEL_FILES: [&str; 10] = [
+    "model.bin",
+    "config.json",
+    "shared_vocabulary.json",
+    "shared_vocabulary.txt",
+    "source_vocabulary.json",
+    "source_vocabulary.txt",
+    "target_vocabulary.json",
+    "target_vocabulary.txt",
+    "vocabulary.json",
+    "vocabulary.txt",
+];
+
+/// Source of the files of a model, e.g. entries of an encrypted archive decrypted on demand.
+pub trait ModelReader {
+    /// Name of the model in CTranslate2 error messages.
+    fn model_id(&self) -> String {
+        "memory".to_string()
+    }
+
+    /// Contents of a model file, `None` if the model does not have it.
+    fn get_file(&mut self, name: &str) -> Result<Option<Vec<u8>>, String>;
+}
+
+/// Files keyed by name.
+impl ModelReader for HashMap<String, Vec<u8>> {
+    fn get_file(&mut self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.get(name).cloned())
+    }
+}
+
+/// The [`MODEL_FILES`] a reader has. Fails if `model.bin` is missing.
+pub fn read_model_files<R: ModelReader>(reader: &mut R) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut res = vec![];
+    for name in MODEL_FILES {
+        if let Some(content) = reader.get_file(name)? {
+            res.push((name.to_string(), content));
+        }
+    }
+    if res.first().is_none_or(|(name, _)| name != "model.bin") {
+        return Err(format!("{}: model.bin is missing", reader.model_id()));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Files "encrypted" by flipping every bit.
+    struct Flipped(HashMap<String, Vec<u8>>);
+
+    impl ModelReader for Flipped {
+        fn model_id(&self) -> String {
+            "flipped".to_string()
+        }
+
+        fn get_file(&mut self, name: &str) -> Result<Option<Vec<u8>>, String> {
+            Ok(self.0.get(name).map(|v| v.iter().map(|b| !b).collect()))
+        }
+    }
+
+    #[test]
+    fn reads_present_files() {
+        let mut files = HashMap::new();
+        files.insert("config.json".to_string(), b"{}".to_vec());
+        files.insert("notes.txt".to_string(), b"ignored".to_vec());
+        assert_eq!(
+            read_model_files(&mut files),
+            Err("memory: model.bin is missing".to_string())
+        );
+
+        files.insert("model.bin".to_string(), vec![1, 2]);
+        let flipped = files
+            .iter()
+            .map(|(k, v)| (k.clone(), v.iter().map(|b| !b).collect()))
+            .collect();
+        let output = read_model_files(&mut Flipped(flipped)).unwrap();
+        assert_eq!(
+            output,
+            vec![
+                ("model.bin".to_string(), vec![1, 2]),
+                ("config.json".to_string(), b"{}".to_vec())
+            ]
+        );
+    }
+}
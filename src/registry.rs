@@ -0,0 +1,342 @@
+//! Registry of the models of many language pairs.
+//!
+//! Models are discovered from a directory, loaded on first use, evicted when idle or when the
+//! memory budget is exceeded, and pairs without a direct model are translated through a pivot
+//! language (English by default).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+/// Manifest listing the models of a registry directory, one `source target path` line per model.
+pub const REGISTRY_MANIFEST: &str = "registry.txt";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelEntry {
+    pub source: String,
+    pub target: String,
+    pub path: PathBuf,
+    /// Estimated memory use: the size of `model.bin`.
+    pub size: u64,
+}
+
+impl ModelEntry {
+    pub fn new(source: &str, target: &str, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let size = fs::metadata(path.join("model.bin"))
+            .map(|v| v.len())
+            .unwrap_or(0);
+        Self {
+            source: source.to_lowercase(),
+            target: target.to_lowercase(),
+            path,
+            size,
+        }
+    }
+}
+
+struct Loaded<T, K> {
+    translator: T,
+    tokenizer: K,
+    last_used: Instant,
+}
+
+type Loader<T, K> = Box<dyn FnMut(&ModelEntry) -> Result<(T, K), String> + Send>;
+
+pub struct ModelRegistry<T: Translate, K: Tokenizer> {
+    entries: Vec<ModelEntry>,
+    loaded: HashMap<(String, String), Loaded<T, K>>,
+    loader: Loader<T, K>,
+    /// Maximum total size of the loaded models, in bytes.
+    pub memory_budget: Option<u64>,
+    /// Language used to translate pairs without a direct model.
+    pub pivot: Option<String>,
+}
+
+impl<T: Translate, K: Tokenizer> ModelRegistry<T, K> {
+    /// `loader` loads the translator and tokenizer of a model, e.g. a [`crate::CTranslator`] and
+    /// the SentencePiece models of the directory.
+    pub fn new(
+        entries: Vec<ModelEntry>,
+        loader: impl FnMut(&ModelEntry) -> Result<(T, K), String> + Send + 'static,
+    ) -> Self {
+        Self {
+            entries,
+            loaded: HashMap::new(),
+            loader: Box::new(loader),
+            memory_budget: None,
+            pivot: Some("en".to_string()),
+        }
+    }
+
+    /// Models listed in the [`REGISTRY_MANIFEST`] of `root`, or else the sub-directories
+    /// containing a `model.bin` and named after their language pair (`en-de`, `opus-mt-en-de`).
+    pub fn discover(
+        root: impl AsRef<Path>,
+        loader: impl FnMut(&ModelEntry) -> Result<(T, K), String> + Send + 'static,
+    ) -> Result<Self, String> {
+        let root = root.as_ref();
+        let manifest = root.join(REGISTRY_MANIFEST);
+        let entries = if manifest.exists() {
+            let text = fs::read_to_string(manifest).map_err(|e| e.to_string())?;
+            parse_manifest(root, &text)?
+        } else {
+            let mut entries = vec![];
+            for dir in fs::read_dir(root).map_err(|e| e.to_string())? {
+                let path = dir.map_err(|e| e.to_string())?.path();
+                if !path.join("model.bin").is_file() {
+                    continue;
+                }
+                let name = path.file_name().and_then(|v| v.to_str()).unwrap_or("");
+                if let Some((source, target)) = language_pair(name) {
+                    entries.push(ModelEntry::new(source, target, path.clone()));
+                }
+            }
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+            entries
+        };
+        Ok(Self::new(entries, loader))
+    }
+
+    pub fn register(&mut self, entry: ModelEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[ModelEntry] {
+        &self.entries
+    }
+
+    /// Language pairs of the loaded models.
+    pub fn loaded(&self) -> Vec<(String, String)> {
+        let mut res: Vec<_> = self.loaded.keys().cloned().collect();
+        res.sort();
+        res
+    }
+
+    pub fn loaded_size(&self) -> u64 {
+        self.loaded
+            .keys()
+            .filter_map(|pair| self.entry(&pair.0, &pair.1))
+            .map(|v| v.size)
+            .sum()
+    }
+
+    fn entry(&self, source: &str, target: &str) -> Option<&ModelEntry> {
+        self.entries
+            .iter()
+            .find(|v| v.source == source && v.target == target)
+    }
+
+    /// Language pairs of the models translating from `source` to `target`, directly or through
+    /// the pivot language.
+    pub fn route(&self, source: &str, target: &str) -> Option<Vec<(String, String)>> {
+        let (source, target) = (source.to_lowercase(), target.to_lowercase());
+        if self.entry(&source, &target).is_some() {
+            return Some(vec![(source, target)]);
+        }
+        let pivot = self.pivot.as_ref()?.to_lowercase();
+        if pivot == source || pivot == target {
+            return None;
+        }
+        self.entry(&source, &pivot)?;
+        self.entry(&pivot, &target)?;
+        Some(vec![(source, pivot.clone()), (pivot, target)])
+    }
+
+    /// Translates texts from `source` to `target`, loading the models of the route if needed.
+    pub fn translate(
+        &mut self,
+        source: &str,
+        target: &str,
+        input: &[String],
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<String>, String> {
+        let route = self
+            .route(source, target)
+            .ok_or_else(|| format!("no model for {}-{}", source, target))?;
+        for pair in &route {
+            self.load(pair, &route)?;
+        }
+        let mut texts = input.to_vec();
+        for pair in &route {
+            let model = self.loaded.get_mut(pair).unwrap();
+            model.last_used = Instant::now();
+            let batch = texts.iter().map(|v| model.tokenizer.encode(v)).collect();
+            let output = model.translator.translate_batch(
+                batch,
+                max_batch_size,
+                options.clone(),
+                batch_type,
+            )?;
+            texts = output.iter().map(|v| model.tokenizer.decode(v)).collect();
+        }
+        Ok(texts)
+    }
+
+    /// Unloads the models unused for longer than `max_idle`.
+    pub fn evict_idle(&mut self, max_idle: Duration) {
+        self.loaded.retain(|_, v| v.last_used.elapsed() <= max_idle);
+    }
+
+    fn load(&mut self, pair: &(String, String), keep: &[(String, String)]) -> Result<(), String> {
+        if self.loaded.contains_key(pair) {
+            return Ok(());
+        }
+        let entry = self.entry(&pair.0, &pair.1).unwrap().clone();
+        if let Some(budget) = self.memory_budget {
+            // Least recently used first; a model larger than the budget is loaded anyway.
+            while self.loaded_size() + entry.size > budget {
+                let oldest = self
+                    .loaded
+                    .iter()
+                    .filter(|(k, _)| !keep.contains(k))
+                    .min_by_key(|(_, v)| v.last_used)
+                    .map(|(k, _)| k.clone());
+                match oldest {
+                    Some(k) => self.loaded.remove(&k),
+                    None => break,
+                };
+            }
+        }
+        let (translator, tokenizer) = (self.loader)(&entry)?;
+        self.loaded.insert(
+            pair.clone(),
+            Loaded {
+                translator,
+                tokenizer,
+                last_used: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Language pair from the end of a directory name, e.g. `opus-mt-en-de` or `base_en_ja`.
+fn language_pair(name: &str) -> Option<(&str, &str)> {
+    let parts: Vec<&str> = name.split(['-', '_']).collect();
+    let is_code =
+        |v: &str| (2..=3).contains(&v.len()) && v.chars().all(|c| c.is_ascii_alphabetic());
+    match parts[..] {
+        [.., source, target] if is_code(source) && is_code(target) => Some((source, target)),
+        _ => None,
+    }
+}
+
+fn parse_manifest(root: &Path, text: &str) -> Result<Vec<ModelEntry>, String> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [source, target, path] = fields[..] else {
+            return Err(format!(
+                "{} line {}: expected `source target path`",
+                REGISTRY_MANIFEST,
+                i + 1
+            ));
+        };
+        entries.push(ModelEntry::new(source, target, root.join(path)));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    fn registry(entries: Vec<ModelEntry>) -> ModelRegistry<MockTranslator, WhitespaceTokenizer> {
+        ModelRegistry::new(entries, |entry| {
+            let tag = format!("{}>{}", entry.source, entry.target);
+            let translator =
+                MockTranslator::new(move |v| v.iter().map(|t| format!("{}{}", t, tag)).collect());
+            Ok((translator, WhitespaceTokenizer))
+        })
+    }
+
+    #[test]
+    fn discovers_models() {
+        let root =
+            std::env::temp_dir().join(format!("rustyctranslate2-registry-{}", std::process::id()));
+        for dir in ["base-en-ja", "opus-mt-de-en", "notes"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("base-en-ja/model.bin"), [0u8; 16]).unwrap();
+        fs::write(root.join("opus-mt-de-en/model.bin"), [0u8; 8]).unwrap();
+        let discovered = registry(vec![]);
+        let discovered = ModelRegistry::discover(&root, discovered.loader).unwrap();
+        let pairs: Vec<_> = discovered
+            .entries()
+            .iter()
+            .map(|v| (v.source.as_str(), v.target.as_str(), v.size))
+            .collect();
+        assert_eq!(pairs, vec![("en", "ja", 16), ("de", "en", 8)]);
+
+        fs::write(root.join(REGISTRY_MANIFEST), "# pairs\nen ja base-en-ja\n").unwrap();
+        let manifest =
+            ModelRegistry::discover(&root, |_| Ok((MockTranslator::echo(), WhitespaceTokenizer)))
+                .unwrap();
+        assert_eq!(manifest.entries().len(), 1);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn routes_through_pivot_and_evicts() {
+        let entry = |s: &str, t: &str| ModelEntry {
+            source: s.to_string(),
+            target: t.to_string(),
+            path: PathBuf::from(format!("{}-{}", s, t)),
+            size: 10,
+        };
+        let mut registry = registry(vec![
+            entry("de", "en"),
+            entry("en", "ja"),
+            entry("fr", "en"),
+        ]);
+        registry.memory_budget = Some(20);
+        assert_eq!(registry.route("en", "ja").unwrap().len(), 1);
+        assert_eq!(registry.route("DE", "ja").unwrap().len(), 2);
+        assert!(registry.route("ja", "de").is_none());
+
+        let output = registry
+            .translate(
+                "de",
+                "ja",
+                &["hallo".to_string()],
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(output, vec!["hallode>enen>ja"]);
+        assert_eq!(registry.loaded().len(), 2);
+
+        registry
+            .translate(
+                "fr",
+                "en",
+                &["salut".to_string()],
+                None,
+                None,
+                BatchType::Example,
+            )
+            .unwrap();
+        assert_eq!(registry.loaded_size(), 20);
+        assert!(registry
+            .loaded()
+            .contains(&("fr".to_string(), "en".to_string())));
+
+        std::thread::sleep(Duration::from_millis(2));
+        registry.evict_idle(Duration::from_millis(1));
+        assert!(registry.loaded().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+//! Hot reloading of models.
+//!
+//! A [`ReloadableTranslator`] is a shared handle to the current model. Reloading loads the new
+//! model next to the old one and swaps it in once it passes a smoke translation; calls already
+//! running keep the old model, which is freed when the last of them finishes.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::{BatchType, CTranslator, Translate, TranslationOptions, TranslationResult};
+
+type Loader<T> = Arc<dyn Fn(&Path) -> Result<T, String> + Send + Sync>;
+
+struct Model<T> {
+    translator: Mutex<T>,
+    path: PathBuf,
+}
+
+/// Handle to a model that can be replaced while it is in use. Clones share the model.
+pub struct ReloadableTranslator<T> {
+    current: Arc<RwLock<Arc<Model<T>>>>,
+    loader: Loader<T>,
+    smoke_test: Arc<Vec<Vec<String>>>,
+}
+
+impl<T> Clone for ReloadableTranslator<T> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+            loader: self.loader.clone(),
+            smoke_test: self.smoke_test.clone(),
+        }
+    }
+}
+
+impl ReloadableTranslator<CTranslator> {
+    pub fn new(path: PathBuf, use_gpu: bool, compressed: bool) -> Result<Self, String> {
+        Self::with_loader(path, move |path| {
+            CTranslator::new(path.to_path_buf(), use_gpu, compressed)
+        })
+    }
+}
+
+impl<T: Translate + Send + 'static> ReloadableTranslator<T> {
+    /// Loads the first model with `loader`, which also loads the later ones.
+    pub fn with_loader(
+        path: PathBuf,
+        loader: impl Fn(&Path) -> Result<T, String> + Send + Sync + 'static,
+    ) -> Result<Self, String> {
+        let loader: Loader<T> = Arc::new(loader);
+        let smoke_test = Arc::new(vec![vec!["▁Hello".to_string(), "▁world".to_string()]]);
+        let model = load(&loader, &smoke_test, &path)?;
+        Ok(Self {
+            current: Arc::new(RwLock::new(Arc::new(model))),
+            loader,
+            smoke_test,
+        })
+    }
+
+    /// Examples a new model must translate before it is swapped in.
+    pub fn with_smoke_test(mut self, input: Vec<Vec<String>>) -> Self {
+        self.smoke_test = Arc::new(input);
+        self
+    }
+
+    /// Directory of the current model.
+    pub fn path(&self) -> PathBuf {
+        self.current.read().unwrap().path.clone()
+    }
+
+    /// Loads the model of a directory and swaps it in. If loading or the smoke translation fails,
+    /// the current model stays in place and the error is returned.
+    pub fn reload(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let model = load(&self.loader, &self.smoke_test, path.as_ref())?;
+        *self.current.write().unwrap() = Arc::new(model);
+        Ok(())
+    }
+
+    /// [`Self::reload`] on another thread, translations continue on the current model meanwhile.
+    pub fn reload_in_background(&self, path: PathBuf) -> JoinHandle<Result<(), String>> {
+        let handle = self.clone();
+        thread::spawn(move || handle.reload(path))
+    }
+
+    /// Reloads the current directory whenever one of its files changes, checking every `interval`.
+    /// Watching stops when the returned [`Watcher`] is dropped.
+    pub fn watch(&self, interval: Duration) -> Watcher {
+        let handle = self.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let mut last = fingerprint(&handle.path());
+        let thread = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                let path = handle.path();
+                let current = fingerprint(&path);
+                if current != last {
+                    // A failed reload (e.g. files still being copied) is retried on the next change.
+                    let _ = handle.reload(&path);
+                    last = current;
+                }
+            }
+        });
+        Watcher {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T: Translate + Send + 'static> Translate for ReloadableTranslator<T> {
+    fn translate_batch_results(
+        &mut self,
+        input: Vec<Vec<String>>,
+        max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        batch_type: BatchType,
+    ) -> Result<Vec<TranslationResult>, String> {
+        let model = self.current.read().unwrap().clone();
+        let mut translator = model.translator.lock().unwrap();
+        translator.translate_batch_results(input, max_batch_size, options, batch_type)
+    }
+}
+
+/// Background directory watch of [`ReloadableTranslator::watch`].
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn load<T: Translate>(
+    loader: &Loader<T>,
+    smoke_test: &[Vec<String>],
+    path: &Path,
+) -> Result<Model<T>, String> {
+    let mut translator = loader(path)?;
+    if !smoke_test.is_empty() {
+        let output = translator
+            .translate_batch_results(smoke_test.to_vec(), None, None, BatchType::Example)
+            .map_err(|e| format!("smoke translation failed: {}", e))?;
+        if output.len() != smoke_test.len() || output.iter().any(|v| v.hypotheses.is_empty()) {
+            return Err("smoke translation returned no output".to_string());
+        }
+    }
+    Ok(Model {
+        translator: Mutex::new(translator),
+        path: path.to_path_buf(),
+    })
+}
+
+/// Name, size and modification time of the files of a directory.
+fn fingerprint(path: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    let mut res: Vec<_> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.len(), metadata.modified().ok()))
+        })
+        .collect();
+    res.sort();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+
+    /// The "model" is the name of a [`MockTranslator`] constructor in `model.bin`.
+    fn loader(path: &Path) -> Result<MockTranslator, String> {
+        let name = fs::read_to_string(path.join("model.bin")).map_err(|e| e.to_string())?;
+        match name.trim() {
+            "echo" => Ok(MockTranslator::echo()),
+            "upper" => Ok(MockTranslator::upper()),
+            "broken" => {
+                let mut translator = MockTranslator::echo();
+                translator.fail_on = Some("▁Hello".to_string());
+                Ok(translator)
+            }
+            other => Err(format!("unknown model {}", other)),
+        }
+    }
+
+    fn model_dir(name: &str, model: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rustyctranslate2-reload-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("model.bin"), model).unwrap();
+        dir
+    }
+
+    fn translate(translator: &mut ReloadableTranslator<MockTranslator>) -> String {
+        let output = translator
+            .translate_batch(vec![vec!["a".to_string()]], None, None, BatchType::Example)
+            .unwrap();
+        output[0].join(" ")
+    }
+
+    #[test]
+    fn swaps_and_rolls_back() {
+        let (old, new, broken) = (
+            model_dir("old", "echo"),
+            model_dir("new", "upper"),
+            model_dir("broken", "broken"),
+        );
+        let mut translator = ReloadableTranslator::with_loader(old.clone(), loader).unwrap();
+        assert_eq!(translate(&mut translator), "a");
+
+        assert!(translator.reload(&broken).is_err());
+        assert_eq!(translator.path(), old);
+        assert_eq!(translate(&mut translator), "a");
+
+        let reload = translator.reload_in_background(new.clone());
+        reload.join().unwrap().unwrap();
+        assert_eq!(translator.path(), new);
+        assert_eq!(translate(&mut translator.clone()), "A");
+
+        for dir in [old, new, broken] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn reloads_on_change() {
+        let dir = model_dir("watched", "echo");
+        let mut translator = ReloadableTranslator::with_loader(dir.clone(), loader).unwrap();
+        let watcher = translator.watch(Duration::from_millis(5));
+        fs::write(dir.join("model.bin"), "upper").unwrap();
+        for _ in 0..200 {
+            if translate(&mut translator) == "A" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(translate(&mut translator), "A");
+        drop(watcher);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
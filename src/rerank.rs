@@ -0,0 +1,368 @@
+//! Reranking of the n-best hypotheses returned with `num_hypotheses > 1` by more than the model
+//! score.
+
+use crate::glossary::Glossary;
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Score, Translate, TranslationOptions, TranslationResult};
+
+/// One hypothesis of an example.
+#[derive(Clone, Copy, Debug)]
+pub struct Candidate<'a> {
+    pub source: &'a [String],
+    pub hypothesis: &'a [String],
+    /// Model score of the hypothesis, if scores were requested.
+    pub score: Option<f32>,
+}
+
+/// Scores candidates, higher is better.
+///
+/// All candidates of a batch are passed at once so model based scorers can run a single batch.
+pub trait Scorer {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String>;
+}
+
+/// The score returned by the translation model.
+pub struct ModelScorer;
+
+impl Scorer for ModelScorer {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String> {
+        Ok(candidates.iter().map(|v| v.score.unwrap_or(0.0)).collect())
+    }
+}
+
+/// Penalizes hypotheses whose length deviates from the expected ratio to the source length:
+/// `-|ln(target / source / ratio)|`.
+pub struct LengthRatioScorer {
+    /// Expected target tokens per source token.
+    pub ratio: f32,
+}
+
+impl Default for LengthRatioScorer {
+    fn default() -> Self {
+        Self { ratio: 1.0 }
+    }
+}
+
+impl Scorer for LengthRatioScorer {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String> {
+        Ok(candidates
+            .iter()
+            .map(|v| {
+                let source = v.source.len().max(1) as f32;
+                let target = v.hypothesis.len().max(1) as f32;
+                -(target / source / self.ratio).ln().abs()
+            })
+            .collect())
+    }
+}
+
+/// Share of the glossary terms found in the source whose target appears in the hypothesis
+/// (1 without terms).
+pub struct GlossaryScorer<'a, K: Tokenizer> {
+    pub glossary: &'a Glossary,
+    pub tokenizer: &'a K,
+}
+
+impl<K: Tokenizer> Scorer for GlossaryScorer<'_, K> {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String> {
+        Ok(candidates
+            .iter()
+            .map(|v| {
+                let matches = self.glossary.find(v.source);
+                if matches.is_empty() {
+                    return 1.0;
+                }
+                let text = self.tokenizer.decode(v.hypothesis);
+                let present = matches
+                    .iter()
+                    .filter(|m| {
+                        self.glossary
+                            .contains(&text, &self.glossary.terms[m.term].target)
+                    })
+                    .count();
+                present as f32 / matches.len() as f32
+            })
+            .collect())
+    }
+}
+
+/// Length-normalized log-prob of the source given the hypothesis under a reverse
+/// (target to source) model.
+pub struct ReverseScorer<'a, T: Score> {
+    pub translator: &'a mut T,
+    pub max_batch_size: Option<usize>,
+    pub batch_type: BatchType,
+}
+
+impl<'a, T: Score> ReverseScorer<'a, T> {
+    pub fn new(translator: &'a mut T) -> Self {
+        Self {
+            translator,
+            max_batch_size: None,
+            batch_type: BatchType::default(),
+        }
+    }
+}
+
+impl<T: Score> Scorer for ReverseScorer<'_, T> {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String> {
+        let source = candidates.iter().map(|v| v.hypothesis.to_vec()).collect();
+        let target = candidates.iter().map(|v| v.source.to_vec()).collect();
+        Ok(self
+            .translator
+            .score_batch(source, target, self.max_batch_size, self.batch_type)?
+            .iter()
+            .map(|v| v.normalized_score())
+            .collect())
+    }
+}
+
+/// Minus the share of repeated n-grams in the hypothesis.
+pub struct RepetitionScorer {
+    pub n: usize,
+}
+
+impl Default for RepetitionScorer {
+    fn default() -> Self {
+        Self { n: 2 }
+    }
+}
+
+impl Scorer for RepetitionScorer {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String> {
+        let n = self.n.max(1);
+        Ok(candidates
+            .iter()
+            .map(|v| {
+                let ngrams: Vec<&[String]> = v.hypothesis.windows(n).collect();
+                if ngrams.is_empty() {
+                    return 0.0;
+                }
+                let repeated = ngrams
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, g)| ngrams[..*i].contains(g))
+                    .count();
+                -(repeated as f32) / ngrams.len() as f32
+            })
+            .collect())
+    }
+}
+
+/// Weighted sum of several scorers.
+#[derive(Default)]
+pub struct WeightedScorer<'a> {
+    scorers: Vec<(Box<dyn Scorer + 'a>, f32)>,
+}
+
+impl<'a> WeightedScorer<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, scorer: impl Scorer + 'a, weight: f32) -> Self {
+        self.scorers.push((Box::new(scorer), weight));
+        self
+    }
+}
+
+impl Scorer for WeightedScorer<'_> {
+    fn score(&mut self, candidates: &[Candidate]) -> Result<Vec<f32>, String> {
+        let mut res = vec![0.0; candidates.len()];
+        for (scorer, weight) in &mut self.scorers {
+            let scores = scorer.score(candidates)?;
+            if scores.len() != candidates.len() {
+                return Err(format!(
+                    "scorer returned {} scores for {} candidates",
+                    scores.len(),
+                    candidates.len()
+                ));
+            }
+            for (v, s) in res.iter_mut().zip(scores) {
+                *v += *weight * s;
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Result with its hypotheses (and their model scores and attention) reordered best first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reranked {
+    pub result: TranslationResult,
+    /// Reranking score of each hypothesis.
+    pub scores: Vec<f32>,
+}
+
+/// Reorders the hypotheses of each result by the scorer; ties keep the model order.
+pub fn rerank(
+    scorer: &mut dyn Scorer,
+    sources: &[Vec<String>],
+    results: Vec<TranslationResult>,
+) -> Result<Vec<Reranked>, String> {
+    if sources.len() != results.len() {
+        return Err(format!(
+            "{} sources but {} results",
+            sources.len(),
+            results.len()
+        ));
+    }
+    let candidates: Vec<Candidate> = sources
+        .iter()
+        .zip(&results)
+        .flat_map(|(source, result)| {
+            result
+                .hypotheses
+                .iter()
+                .enumerate()
+                .map(move |(i, hypothesis)| Candidate {
+                    source,
+                    hypothesis,
+                    score: result.scores.get(i).copied(),
+                })
+        })
+        .collect();
+    let mut scores = scorer.score(&candidates)?.into_iter();
+    if scores.len() != candidates.len() {
+        return Err(format!(
+            "scorer returned {} scores for {} candidates",
+            scores.len(),
+            candidates.len()
+        ));
+    }
+
+    let mut res = vec![];
+    for result in results {
+        let scores: Vec<f32> = scores.by_ref().take(result.hypotheses.len()).collect();
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        let pick = |v: &[_]| -> Vec<_> {
+            if v.len() == order.len() {
+                order.iter().map(|&i| v[i]).collect()
+            } else {
+                v.to_vec()
+            }
+        };
+        res.push(Reranked {
+            scores: pick(&scores),
+            result: TranslationResult {
+                hypotheses: order
+                    .iter()
+                    .map(|&i| result.hypotheses[i].clone())
+                    .collect(),
+                scores: pick(&result.scores),
+                attention: if result.attention.len() == order.len() {
+                    order.iter().map(|&i| result.attention[i].clone()).collect()
+                } else {
+                    result.attention
+                },
+            },
+        });
+    }
+    Ok(res)
+}
+
+/// Translates a batch and reranks the n-best hypotheses; `return_scores` is forced on.
+pub fn translate_reranked<T: Translate>(
+    translator: &mut T,
+    scorer: &mut dyn Scorer,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<Vec<Reranked>, String> {
+    let mut options = options.unwrap_or_default();
+    options.return_scores = true;
+    let results = translator.translate_batch_results(
+        input.clone(),
+        max_batch_size,
+        Some(options),
+        batch_type,
+    )?;
+    rerank(scorer, &input, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glossary::Term;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    fn tokens(text: &str) -> Vec<String> {
+        WhitespaceTokenizer.encode(text)
+    }
+
+    #[test]
+    fn builtin_scorers() {
+        let source = tokens("the red car");
+        let hypotheses = [tokens("das rote Auto"), tokens("das das das das das das")];
+        let candidates: Vec<Candidate> = hypotheses
+            .iter()
+            .map(|v| Candidate {
+                source: &source,
+                hypothesis: v,
+                score: Some(-1.0),
+            })
+            .collect();
+
+        assert_eq!(ModelScorer.score(&candidates).unwrap(), vec![-1.0, -1.0]);
+        let lengths = LengthRatioScorer::default().score(&candidates).unwrap();
+        assert_eq!(lengths[0], 0.0);
+        assert!((lengths[1] + 2f32.ln()).abs() < 1e-6);
+        let repetition = RepetitionScorer::default().score(&candidates).unwrap();
+        assert_eq!(repetition, vec![0.0, -0.8]);
+
+        let glossary = Glossary::new(vec![Term {
+            source: "car".to_string(),
+            target: "Auto".to_string(),
+        }]);
+        let mut scorer = GlossaryScorer {
+            glossary: &glossary,
+            tokenizer: &WhitespaceTokenizer,
+        };
+        assert_eq!(scorer.score(&candidates).unwrap(), vec![1.0, 0.0]);
+
+        let mut reverse = MockTranslator::echo();
+        let scores = ReverseScorer::new(&mut reverse).score(&candidates).unwrap();
+        assert!(scores[0] < 0.0 && scores[0] == scores[1]);
+    }
+
+    #[test]
+    fn reranks_hypotheses() {
+        let source = tokens("a b");
+        let result = TranslationResult {
+            hypotheses: vec![tokens("x x x x x x"), tokens("x y"), tokens("y")],
+            scores: vec![-0.1, -0.2, -0.3],
+            attention: vec![],
+        };
+        let mut scorer = WeightedScorer::new()
+            .with(ModelScorer, 1.0)
+            .with(LengthRatioScorer::default(), 0.5)
+            .with(RepetitionScorer::default(), 1.0);
+        let reranked = rerank(&mut scorer, &[source], vec![result]).unwrap();
+        let output = &reranked[0].result;
+        assert_eq!(
+            output.hypotheses,
+            vec![tokens("x y"), tokens("y"), tokens("x x x x x x")]
+        );
+        assert_eq!(output.scores, vec![-0.2, -0.3, -0.1]);
+        assert!(reranked[0].scores.windows(2).all(|v| v[0] >= v[1]));
+
+        let mut translator = MockTranslator::echo();
+        let options = TranslationOptions::beam(2);
+        let reranked = translate_reranked(
+            &mut translator,
+            &mut ModelScorer,
+            vec![tokens("a b")],
+            None,
+            Some(TranslationOptions {
+                num_hypotheses: 2,
+                ..options
+            }),
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(reranked[0].scores, vec![-1.0, -1.0]);
+    }
+}
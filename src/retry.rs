@@ -0,0 +1,157 @@
+//! Fault tolerant translation: batches failing with an out of memory error are split in half and
+//! retried, down to single examples.
+
+use crate::{BatchType, Translate, TranslationOptions, TranslationResult};
+
+/// Messages of allocation failures from the C++ side: `std::bad_alloc`, CUDA, cuBLAS and oneDNN.
+const OUT_OF_MEMORY: [&str; 8] = [
+    "bad_alloc",
+    "out of memory",
+    "cannot allocate",
+    "failed to allocate",
+    "allocation failed",
+    "alloc_failed",
+    "memory allocation",
+    "insufficient memory",
+];
+
+/// Whether an error returned by the translator is an allocation failure.
+pub fn is_out_of_memory(error: &str) -> bool {
+    let error = error.to_lowercase();
+    OUT_OF_MEMORY.iter().any(|v| error.contains(v))
+}
+
+/// Translates a batch, splitting it recursively on out of memory errors.
+///
+/// Returns one result per example: examples failing alone, or in a batch failing with another
+/// error, get that error while the rest of the batch is still translated.
+pub fn translate_with_retry<T: Translate>(
+    translator: &mut T,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Vec<Result<TranslationResult, String>> {
+    translate_bisecting(
+        translator,
+        input,
+        max_batch_size,
+        options,
+        batch_type,
+        is_out_of_memory,
+    )
+}
+
+/// Translates a batch, splitting it in half on the errors selected by `split_on`.
+pub(crate) fn translate_bisecting<T: Translate>(
+    translator: &mut T,
+    input: Vec<Vec<String>>,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+    split_on: fn(&str) -> bool,
+) -> Vec<Result<TranslationResult, String>> {
+    if input.is_empty() {
+        return vec![];
+    }
+    let len = input.len();
+    match translator.translate_batch_results(
+        input.clone(),
+        max_batch_size,
+        options.clone(),
+        batch_type,
+    ) {
+        Ok(output) if output.len() == len => output.into_iter().map(Ok).collect(),
+        Ok(_) => vec![Err("translator returned a wrong number of results".to_string()); len],
+        Err(e) if len > 1 && split_on(&e) => {
+            let mut first = input;
+            let second = first.split_off(len / 2);
+            let mut res = translate_bisecting(
+                translator,
+                first,
+                max_batch_size,
+                options.clone(),
+                batch_type,
+                split_on,
+            );
+            res.extend(translate_bisecting(
+                translator,
+                second,
+                max_batch_size,
+                options,
+                batch_type,
+                split_on,
+            ));
+            res
+        }
+        Err(e) => vec![Err(e); len],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails with `std::bad_alloc` when a batch has more than `limit` tokens.
+    struct Limited {
+        limit: usize,
+        calls: usize,
+    }
+
+    impl Translate for Limited {
+        fn translate_batch_results(
+            &mut self,
+            input: Vec<Vec<String>>,
+            _max_batch_size: Option<usize>,
+            _options: Option<TranslationOptions>,
+            _batch_type: BatchType,
+        ) -> Result<Vec<TranslationResult>, String> {
+            self.calls += 1;
+            if input.iter().map(|v| v.len()).sum::<usize>() > self.limit {
+                return Err("std::bad_alloc".to_string());
+            }
+            Ok(input
+                .into_iter()
+                .map(|v| TranslationResult {
+                    hypotheses: vec![v],
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert!(is_out_of_memory("std::bad_alloc"));
+        assert!(is_out_of_memory("CUDA failed with error out of memory"));
+        assert!(is_out_of_memory(
+            "cuBLAS failed with status CUBLAS_STATUS_ALLOC_FAILED"
+        ));
+        assert!(!is_out_of_memory("Index out of range"));
+    }
+
+    #[test]
+    fn splits_on_out_of_memory() {
+        let mut translator = Limited { limit: 4, calls: 0 };
+        let input: Vec<Vec<String>> = [1, 2, 1, 6, 2]
+            .iter()
+            .map(|&n| vec!["x".to_string(); n])
+            .collect();
+        let output = translate_with_retry(&mut translator, input, None, None, BatchType::Example);
+        let lengths: Vec<_> = output
+            .iter()
+            .map(|v| v.as_ref().map(|r| r.output().len()))
+            .collect();
+        assert_eq!(
+            lengths,
+            vec![
+                Ok(1),
+                Ok(2),
+                Ok(1),
+                Err(&"std::bad_alloc".to_string()),
+                Ok(2)
+            ]
+        );
+        assert!(translator.calls > 1);
+    }
+}
@@ -0,0 +1,302 @@
+//! Rule based sentence segmentation for translating paragraphs and documents.
+//!
+//! Rules follow SRX: a rule matches the text before and after a candidate position and decides
+//! whether it is a sentence break. Rules are tried in order and the first match wins; positions
+//! matching no rule are not breaks. Blank lines always end a sentence.
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::tokenizer::Tokenizer;
+use crate::xml;
+use crate::{BatchType, Translate, TranslationOptions};
+
+/// How far rules look before and after a candidate position, in bytes.
+const WINDOW: usize = 128;
+
+const ENGLISH_ABBREVIATIONS: &[&str] = &[
+    "Mr", "Mrs", "Ms", "Dr", "Prof", "Sr", "Jr", "St", "vs", "etc", "e.g", "i.e", "Inc", "Ltd",
+    "Co", "Corp", "No", "Fig", "Vol", "approx", "Jan", "Feb", "Mar", "Apr", "Jun", "Jul", "Aug",
+    "Sep", "Sept", "Oct", "Nov", "Dec",
+];
+const GERMAN_ABBREVIATIONS: &[&str] = &[
+    "Dr", "Prof", "Hr", "Fr", "Nr", "Str", "z.B", "bzw", "usw", "ca", "evtl", "ggf", "inkl",
+    "bzgl", "d.h", "u.a", "vgl", "Abb", "Jh",
+];
+const FRENCH_ABBREVIATIONS: &[&str] = &[
+    "M", "MM", "Mme", "Mlle", "Dr", "Pr", "St", "Ste", "p.ex", "cf", "etc", "env", "av", "bd",
+    "n°", "p",
+];
+const SPANISH_ABBREVIATIONS: &[&str] = &[
+    "Sr", "Sra", "Srta", "Dr", "Dra", "Ud", "Uds", "pág", "etc", "p.ej", "aprox", "núm", "Av",
+];
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    before: Regex,
+    after: Regex,
+    pub is_break: bool,
+}
+
+impl Rule {
+    /// `before` must match the text ending at the position and `after` the text starting there.
+    pub fn new(before: &str, after: &str, is_break: bool) -> Result<Self, String> {
+        Ok(Self {
+            before: Regex::new(&format!("(?:{})$", before)).map_err(|e| e.to_string())?,
+            after: Regex::new(&format!("^(?:{})", after)).map_err(|e| e.to_string())?,
+            is_break,
+        })
+    }
+
+    fn matches(&self, before: &str, after: &str) -> bool {
+        self.before.is_match(before) && self.after.is_match(after)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Segmenter {
+    pub rules: Vec<Rule>,
+}
+
+impl Segmenter {
+    /// Default rules with the abbreviations of a language (`en`, `de`, `fr`, `es`; other languages
+    /// only get the generic rules).
+    pub fn new(language: &str) -> Self {
+        let primary = language
+            .split(['-', '_'])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let abbreviations = match primary.as_str() {
+            "en" => ENGLISH_ABBREVIATIONS,
+            "de" => GERMAN_ABBREVIATIONS,
+            "fr" => FRENCH_ABBREVIATIONS,
+            "es" => SPANISH_ABBREVIATIONS,
+            _ => &[],
+        };
+        let mut rules = vec![];
+        if !abbreviations.is_empty() {
+            let alternatives: Vec<String> =
+                abbreviations.iter().map(|v| regex::escape(v)).collect();
+            rules.push(
+                Rule::new(
+                    &format!(r"(?:^|[^\w.])(?:{})\.", alternatives.join("|")),
+                    r"\s",
+                    false,
+                )
+                .unwrap(),
+            );
+        }
+        let defaults = [
+            // Initials such as "J. Smith".
+            (r"(?:^|\s)\p{Lu}\.", r"\s", false),
+            // The next sentence would start lower case.
+            (r#"[.!?…]+[\p{Pf}\p{Pe}"'’]*"#, r"\s+[\p{Ll}\d]", false),
+            (r#"[.!?…]+[\p{Pf}\p{Pe}"'’]*"#, r"\s", true),
+            (r"[。！？]+[\p{Pf}\p{Pe}」』]*", r".", true),
+        ];
+        rules.extend(
+            defaults
+                .iter()
+                .map(|(before, after, is_break)| Rule::new(before, after, *is_break).unwrap()),
+        );
+        Self { rules }
+    }
+
+    /// Adds a rule taking precedence over the existing ones.
+    pub fn add_rule(&mut self, before: &str, after: &str, is_break: bool) -> Result<(), String> {
+        self.rules.insert(0, Rule::new(before, after, is_break)?);
+        Ok(())
+    }
+
+    /// Rules of a `languagerule` of an SRX document. SRX uses Java regular expressions, rules using
+    /// unsupported syntax (e.g. look-around) are rejected.
+    pub fn from_srx_str(text: &str, language_rule: &str) -> Result<Self, String> {
+        let languagerule = xml::elements(text, "languagerule")
+            .into_iter()
+            .find(|v| v.attribute("languagerulename").as_deref() == Some(language_rule))
+            .ok_or_else(|| format!("no languagerule named {}", language_rule))?;
+        let rules = languagerule
+            .children("rule")
+            .iter()
+            .map(|rule| {
+                let pattern = |name: &str| rule.child(name).map(|v| v.text()).unwrap_or_default();
+                Rule::new(
+                    &pattern("beforebreak"),
+                    &pattern("afterbreak"),
+                    rule.attribute("break").as_deref() != Some("no"),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn from_srx(path: impl AsRef<Path>, language_rule: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::from_srx_str(&text, language_rule)
+    }
+
+    /// Byte ranges of the sentences of a text, without the surrounding whitespace.
+    pub fn split(&self, text: &str) -> Vec<Range<usize>> {
+        let mut res = vec![];
+        let mut start = None;
+        let mut last_end = 0;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                if let Some(s) = start {
+                    if blank_line_follows(&text[i..]) {
+                        res.push(s..i);
+                        start = None;
+                    }
+                }
+                continue;
+            }
+            let s = *start.get_or_insert(i);
+            let end = i + c.len_utf8();
+            last_end = end;
+            if end < text.len() && self.is_break(text, end) {
+                res.push(s..end);
+                start = None;
+            }
+        }
+        if let Some(s) = start {
+            res.push(s..last_end);
+        }
+        res.retain(|r| !r.is_empty());
+        res
+    }
+
+    fn is_break(&self, text: &str, at: usize) -> bool {
+        let before = &text[boundary(text, at.saturating_sub(WINDOW))..at];
+        let after = &text[at..boundary(text, (at + WINDOW).min(text.len()))];
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(before, after))
+            .is_some_and(|rule| rule.is_break)
+    }
+}
+
+/// Whether the whitespace starting the text contains an empty line.
+fn blank_line_follows(text: &str) -> bool {
+    let whitespace = text
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(text.len());
+    text[..whitespace].matches('\n').count() >= 2
+}
+
+fn boundary(text: &str, mut at: usize) -> usize {
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    at
+}
+
+/// Translates a document sentence by sentence in one batch and puts it back together with the
+/// original whitespace between the sentences.
+pub fn translate_document<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    segmenter: &Segmenter,
+    text: &str,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<String, String> {
+    let sentences = segmenter.split(text);
+    if sentences.is_empty() {
+        return Ok(text.to_string());
+    }
+    let batch = sentences
+        .iter()
+        .map(|r| tokenizer.encode(&text[r.clone()]))
+        .collect();
+    let output = translator.translate_batch(batch, max_batch_size, options, batch_type)?;
+    if output.len() != sentences.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+    let mut res = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, tokens) in sentences.into_iter().zip(output) {
+        res.push_str(&text[last..range.start]);
+        res.push_str(&tokenizer.decode(&tokens));
+        last = range.end;
+    }
+    res.push_str(&text[last..]);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    fn sentences<'a>(segmenter: &Segmenter, text: &'a str) -> Vec<&'a str> {
+        segmenter
+            .split(text)
+            .into_iter()
+            .map(|r| &text[r])
+            .collect()
+    }
+
+    #[test]
+    fn splits_sentences() {
+        let segmenter = Segmenter::new("en");
+        assert_eq!(
+            sentences(
+                &segmenter,
+                "Mr. Smith met J. Doe at 3.30 p.m. today. \"Really?\" she asked.  Yes! ok"
+            ),
+            vec![
+                "Mr. Smith met J. Doe at 3.30 p.m. today.",
+                "\"Really?\" she asked.",
+                "Yes! ok"
+            ]
+        );
+        assert_eq!(
+            sentences(&Segmenter::new("ja"), "今日は晴れ。明日は雨？"),
+            vec!["今日は晴れ。", "明日は雨？"]
+        );
+        assert_eq!(
+            sentences(&segmenter, "Title\n\nFirst line\ncontinues here"),
+            vec!["Title", "First line\ncontinues here"]
+        );
+        assert_eq!(
+            sentences(&segmenter, "First. Second part\n"),
+            vec!["First.", "Second part"]
+        );
+    }
+
+    #[test]
+    fn srx_rules_and_document_translation() {
+        let srx = r#"<srx version="2.0"><body><languagerules>
+            <languagerule languagerulename="Default">
+                <rule break="no"><beforebreak>\bfig\.</beforebreak><afterbreak>\s</afterbreak></rule>
+                <rule break="yes"><beforebreak>[.;]</beforebreak><afterbreak>\s</afterbreak></rule>
+            </languagerule>
+        </languagerules></body></srx>"#;
+        let segmenter = Segmenter::from_srx_str(srx, "Default").unwrap();
+        assert_eq!(
+            sentences(&segmenter, "see fig. 2; then stop. done"),
+            vec!["see fig. 2;", "then stop.", "done"]
+        );
+
+        let mut translator = MockTranslator::upper();
+        let text = "  First one.  Second one.\n\nNew paragraph.\n";
+        let output = translate_document(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &Segmenter::new("en"),
+            text,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(output, "  FIRST ONE.  SECOND ONE.\n\nNEW PARAGRAPH.\n");
+        assert_eq!(translator.batches.len(), 1);
+    }
+}
@@ -0,0 +1,420 @@
+//! SRT and WebVTT subtitle translation.
+//!
+//! Cues splitting one sentence are merged and translated together, then the translation is spread
+//! back over the original cues in proportion to their text length, keeping the original timing.
+
+use std::fmt::Write;
+
+use crate::tokenizer::Tokenizer;
+use crate::{BatchType, Translate, TranslationOptions};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cue {
+    /// WebVTT cue identifier; SRT cues are numbered when written.
+    pub id: Option<String>,
+    /// Start and end time in milliseconds.
+    pub start: u64,
+    pub end: u64,
+    /// WebVTT cue settings, e.g. `align:start`.
+    pub settings: String,
+    /// Lines of the cue, separated by `\n`.
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subtitles {
+    pub format: SubtitleFormat,
+    /// WebVTT header and the STYLE, REGION and NOTE blocks before the first cue. NOTE blocks
+    /// between cues are dropped.
+    pub header: String,
+    pub cues: Vec<Cue>,
+}
+
+impl Subtitles {
+    /// Parses WebVTT if the text starts with `WEBVTT`, SRT otherwise.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        let format = if text.starts_with("WEBVTT") {
+            SubtitleFormat::WebVtt
+        } else {
+            SubtitleFormat::Srt
+        };
+        let mut header = String::new();
+        let mut cues = vec![];
+        for block in text.split("\n\n").map(|v| v.trim_matches('\n')) {
+            if block.is_empty() {
+                continue;
+            }
+            let lines: Vec<&str> = block.lines().collect();
+            let Some(timing) = lines.iter().position(|v| v.contains("-->")) else {
+                if format == SubtitleFormat::WebVtt && cues.is_empty() {
+                    header.push_str(block);
+                    header.push_str("\n\n");
+                }
+                continue;
+            };
+            let (times, settings) = parse_timing(lines[timing])?;
+            let id = match (format, timing) {
+                (SubtitleFormat::WebVtt, 1) => Some(lines[0].to_string()),
+                _ => None,
+            };
+            cues.push(Cue {
+                id,
+                start: times.0,
+                end: times.1,
+                settings,
+                text: lines[timing + 1..].join("\n"),
+            });
+        }
+        if format == SubtitleFormat::WebVtt && header.is_empty() {
+            header.push_str("WEBVTT\n\n");
+        }
+        Ok(Self {
+            format,
+            header,
+            cues,
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let mut res = self.header.clone();
+        for (i, cue) in self.cues.iter().enumerate() {
+            let (start, end) = (
+                format_time(cue.start, self.format),
+                format_time(cue.end, self.format),
+            );
+            match self.format {
+                SubtitleFormat::Srt => {
+                    let _ = writeln!(res, "{}\n{} --> {}", i + 1, start, end);
+                }
+                SubtitleFormat::WebVtt => {
+                    if let Some(id) = &cue.id {
+                        let _ = writeln!(res, "{}", id);
+                    }
+                    let _ = write!(res, "{} --> {}", start, end);
+                    if !cue.settings.is_empty() {
+                        let _ = write!(res, " {}", cue.settings);
+                    }
+                    res.push('\n');
+                }
+            }
+            let _ = writeln!(res, "{}\n", cue.text);
+        }
+        res
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubtitleOptions {
+    /// Characters per line; longer lines are wrapped at spaces.
+    pub max_line_length: usize,
+    /// Lines per cue, only exceeded when the text of a cue does not fit otherwise.
+    pub max_lines: usize,
+}
+
+impl Default for SubtitleOptions {
+    fn default() -> Self {
+        Self {
+            max_line_length: 42,
+            max_lines: 2,
+        }
+    }
+}
+
+/// Translates the cues, one batch for the whole file, and returns the subtitles with the
+/// translated text and the original timing.
+#[allow(clippy::too_many_arguments)]
+pub fn translate_subtitles<T: Translate, K: Tokenizer>(
+    translator: &mut T,
+    tokenizer: &K,
+    subtitles: &Subtitles,
+    subtitle_options: &SubtitleOptions,
+    max_batch_size: Option<usize>,
+    options: Option<TranslationOptions>,
+    batch_type: BatchType,
+) -> Result<Subtitles, String> {
+    let groups = sentence_groups(&subtitles.cues);
+    let batch = groups
+        .iter()
+        .map(|group| {
+            let text: Vec<String> = group
+                .iter()
+                .map(|&i| {
+                    subtitles.cues[i]
+                        .text
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect();
+            tokenizer.encode(&text.join(" "))
+        })
+        .collect();
+    let output = translator.translate_batch(batch, max_batch_size, options, batch_type)?;
+    if output.len() != groups.len() {
+        return Err("translator returned a wrong number of results".to_string());
+    }
+
+    let mut res = subtitles.clone();
+    let mut removed = vec![false; res.cues.len()];
+    for (group, tokens) in groups.iter().zip(output) {
+        let weights: Vec<usize> = group
+            .iter()
+            .map(|&i| subtitles.cues[i].text.chars().count().max(1))
+            .collect();
+        let parts = spread(&tokenizer.decode(&tokens), &weights);
+        // A translation with fewer words than cues leaves parts empty: their timing goes to the
+        // previous cue with text (or the next one), since a cue without text is invalid.
+        let filled: Vec<usize> = (0..parts.len()).filter(|&k| !parts[k].is_empty()).collect();
+        for (k, (&i, part)) in group.iter().zip(&parts).enumerate() {
+            if !part.is_empty() {
+                res.cues[i].text = wrap(part, subtitle_options);
+                continue;
+            }
+            removed[i] = true;
+            let owner = filled.iter().rev().find(|&&o| o < k).or(filled.first());
+            if let Some(&o) = owner {
+                let owner = &mut res.cues[group[o]];
+                owner.start = owner.start.min(subtitles.cues[i].start);
+                owner.end = owner.end.max(subtitles.cues[i].end);
+            }
+        }
+    }
+    res.cues = res
+        .cues
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(cue, _)| cue)
+        .collect();
+    Ok(res)
+}
+
+/// Consecutive cues forming one sentence: a cue not ending with terminal punctuation continues in
+/// the next one.
+fn sentence_groups(cues: &[Cue]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut open = false;
+    for (i, cue) in cues.iter().enumerate() {
+        match groups.last_mut() {
+            Some(group) if open => group.push(i),
+            _ => groups.push(vec![i]),
+        }
+        let end = cue
+            .text
+            .trim_end()
+            .trim_end_matches(['"', '\'', '’', '”', ')', '»', '」', '』']);
+        open = !end.is_empty() && !end.ends_with(['.', '!', '?', '…', '。', '！', '？', ':', ';']);
+    }
+    groups
+}
+
+/// Splits a text into one part per weight, at spaces (or between characters for CJK text without
+/// spaces), with part lengths proportional to the weights. Parts are empty when there are fewer
+/// words than weights.
+fn spread(text: &str, weights: &[usize]) -> Vec<String> {
+    let spaced = text.contains(char::is_whitespace) || !text.chars().any(is_cjk);
+    let units: Vec<String> = if spaced {
+        text.split_whitespace().map(|v| v.to_string()).collect()
+    } else {
+        text.chars().map(|v| v.to_string()).collect()
+    };
+    let separator = if spaced { " " } else { "" };
+    let total_weight: usize = weights.iter().sum();
+    let total_len: usize = units.iter().map(|v| v.chars().count()).sum();
+
+    let mut res = vec![];
+    let mut start = 0;
+    let mut cumulative_weight = 0;
+    for (i, weight) in weights.iter().enumerate() {
+        cumulative_weight += weight;
+        let end = if i + 1 == weights.len() {
+            units.len()
+        } else {
+            // Cut where the length so far is closest to the share of the weights so far, leaving
+            // at least one unit for each remaining part when possible.
+            let target = total_len * cumulative_weight / total_weight.max(1);
+            let remaining = weights.len() - i - 1;
+            let mut end = start;
+            let mut len: usize = units[..start].iter().map(|v| v.chars().count()).sum();
+            while end < units.len().saturating_sub(remaining) {
+                let next = len + units[end].chars().count();
+                if end > start && next.abs_diff(target) > len.abs_diff(target) {
+                    break;
+                }
+                len = next;
+                end += 1;
+            }
+            end
+        };
+        res.push(units[start..end].join(separator));
+        start = end;
+    }
+    res
+}
+
+/// Scripts written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{2e80}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '\u{ff00}'..='\u{ffef}')
+}
+
+/// Greedy wrapping at spaces, rebalanced over more lines if the cue needs more than `max_lines`
+/// lines of `max_line_length`.
+fn wrap(text: &str, options: &SubtitleOptions) -> String {
+    let len = text.chars().count();
+    let lines = len.div_ceil(options.max_line_length.max(1)).max(1);
+    // Two short lines read better than one long and one very short line.
+    let width = if lines <= options.max_lines {
+        options.max_line_length
+    } else {
+        len.div_ceil(lines)
+    };
+    let mut res: Vec<String> = vec![];
+    for word in text.split(' ').filter(|v| !v.is_empty()) {
+        match res.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => res.push(word.to_string()),
+        }
+    }
+    res.join("\n")
+}
+
+fn parse_timing(line: &str) -> Result<((u64, u64), String), String> {
+    let (start, rest) = line
+        .split_once("-->")
+        .ok_or_else(|| format!("bad timing line: {}", line))?;
+    let rest = rest.trim();
+    let (end, settings) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Ok((
+        (parse_time(start.trim())?, parse_time(end)?),
+        settings.trim().to_string(),
+    ))
+}
+
+/// `hh:mm:ss,mmm` (SRT) or `[hh:]mm:ss.mmm` (WebVTT), in milliseconds.
+fn parse_time(text: &str) -> Result<u64, String> {
+    let bad = || format!("bad timestamp: {}", text);
+    let (clock, millis) = text.split_once([',', '.']).ok_or_else(bad)?;
+    let parts = clock
+        .split(':')
+        .map(|v| v.parse::<u64>().map_err(|_| bad()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let seconds = match parts[..] {
+        [h, m, s] => h * 3600 + m * 60 + s,
+        [m, s] => m * 60 + s,
+        _ => return Err(bad()),
+    };
+    Ok(seconds * 1000 + millis.parse::<u64>().map_err(|_| bad())?)
+}
+
+fn format_time(millis: u64, format: SubtitleFormat) -> String {
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::WebVtt => '.',
+    };
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTranslator;
+    use crate::tokenizer::WhitespaceTokenizer;
+
+    #[test]
+    fn translates_srt() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\nthis sentence runs\r\n\r\n\
+                   2\r\n00:00:02,600 --> 00:00:04,000\r\nacross two cues.\r\n\r\n\
+                   3\r\n00:01:00,000 --> 01:00:00,001\r\nShort one!\r\n";
+        let subtitles = Subtitles::parse(srt).unwrap();
+        assert_eq!(subtitles.format, SubtitleFormat::Srt);
+        assert_eq!(
+            (subtitles.cues[2].start, subtitles.cues[2].end),
+            (60_000, 3_600_001)
+        );
+        assert_eq!(sentence_groups(&subtitles.cues), vec![vec![0, 1], vec![2]]);
+
+        let mut translator = MockTranslator::upper();
+        let options = SubtitleOptions {
+            max_line_length: 10,
+            max_lines: 2,
+        };
+        let output = translate_subtitles(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &subtitles,
+            &options,
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(translator.translated(), 2);
+        assert_eq!(
+            output.render(),
+            "1\n00:00:01,000 --> 00:00:02,500\nTHIS\nSENTENCE\nRUNS\n\n\
+             2\n00:00:02,600 --> 00:00:04,000\nACROSS TWO\nCUES.\n\n\
+             3\n00:01:00,000 --> 01:00:00,001\nSHORT ONE!\n\n"
+        );
+    }
+
+    #[test]
+    fn round_trips_webvtt() {
+        let vtt = "WEBVTT - demo\n\nSTYLE\n::cue { color: yellow }\n\n\
+                   intro\n00:01.000 --> 00:02.000 align:start line:0\nHello there.\n\n\
+                   NOTE dropped\n\n\
+                   00:02.500 --> 00:04.000\nSecond cue.\n";
+        let subtitles = Subtitles::parse(vtt).unwrap();
+        assert_eq!(subtitles.cues.len(), 2);
+        assert_eq!(subtitles.cues[0].id.as_deref(), Some("intro"));
+        assert_eq!(subtitles.cues[0].settings, "align:start line:0");
+        assert_eq!(
+            subtitles.render(),
+            "WEBVTT - demo\n\nSTYLE\n::cue { color: yellow }\n\n\
+             intro\n00:00:01.000 --> 00:00:02.000 align:start line:0\nHello there.\n\n\
+             00:00:02.500 --> 00:00:04.000\nSecond cue.\n\n"
+        );
+        assert_eq!(
+            spread("今日は晴れです", &[1, 1]),
+            vec!["今日は", "晴れです"]
+        );
+    }
+
+    #[test]
+    fn merges_cues_without_words() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nWell, you know\n\n\
+                   2\n00:00:02,500 --> 00:00:04,000\nwhat I mean.\n";
+        let subtitles = Subtitles::parse(srt).unwrap();
+        let mut translator = MockTranslator::new(|_| vec!["▁Exactly.".to_string()]);
+        let output = translate_subtitles(
+            &mut translator,
+            &WhitespaceTokenizer,
+            &subtitles,
+            &SubtitleOptions::default(),
+            None,
+            None,
+            BatchType::Example,
+        )
+        .unwrap();
+        assert_eq!(
+            output.render(),
+            "1\n00:00:01,000 --> 00:00:04,000\nExactly.\n\n"
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Test support: a translator stand-in for the unit tests of the layers built on [`Translate`], and
+//! a tiny synthetic CTranslate2 model for the tests of [`crate::CTranslator`].
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::model_bin::{self, Tensor};
+use crate::{BatchType, Score, ScoringResult, Translate, TranslationOptions, TranslationResult};
+
+type TranslateFn = Box<dyn FnMut(&[String]) -> Vec<String> + Send>;
+
+/// Translates each example with a function and records every batch it receives.
+///
+/// Attention points every target token at the source token with the same text (ignoring case),
+/// or at the source token in the same position.
+pub struct MockTranslator {
+    f: TranslateFn,
+    /// Batches containing this token fail.
+    pub fail_on: Option<String>,
+    pub batches: Vec<Vec<Vec<String>>>,
+}
+
+impl MockTranslator {
+    pub fn new(f: impl FnMut(&[String]) -> Vec<String> + Send + 'static) -> Self {
+        Self {
+            f: Box::new(f),
+            fail_on: None,
+            batches: vec![],
+        }
+    }
+
+    /// Returns the input unchanged.
+    pub fn echo() -> Self {
+        Self::new(|v| v.to_vec())
+    }
+
+    /// Upper-cases every token.
+    pub fn upper() -> Self {
+        Self::new(|v| v.iter().map(|t| t.to_uppercase()).collect())
+    }
+
+    /// Total number of examples translated.
+    pub fn translated(&self) -> usize {
+        self.batches.iter().map(|v| v.len()).sum()
+    }
+}
+
+impl Translate for MockTranslator {
+    fn translate_batch_results(
+        &mut self,
+        input: Vec<Vec<String>>,
+        _max_batch_size: Option<usize>,
+        options: Option<TranslationOptions>,
+        _batch_type: BatchType,
+    ) -> Result<Vec<TranslationResult>, String> {
+        if let Some(token) = &self.fail_on {
+            if input.iter().flatten().any(|v| v == token) {
+                return Err(format!("unsupported token {}", token));
+            }
+        }
+        let options = options.unwrap_or_default();
+        let results = input
+            .iter()
+            .map(|source| {
+                let target = (self.f)(source);
+                let attention = target
+                    .iter()
+                    .enumerate()
+                    .map(|(i, token)| {
+                        let aligned = source
+                            .iter()
+                            .position(|s| s.to_lowercase() == token.to_lowercase())
+                            .unwrap_or(i.min(source.len().saturating_sub(1)));
+                        (0..source.len())
+                            .map(|j| if j == aligned { 1.0 } else { 0.0 })
+                            .collect()
+                    })
+                    .collect();
+                TranslationResult {
+                    hypotheses: vec![target; options.num_hypotheses.max(1)],
+                    scores: if options.return_scores {
+                        vec![-1.0; options.num_hypotheses.max(1)]
+                    } else {
+                        vec![]
+                    },
+                    attention: if options.return_attention {
+                        vec![attention; options.num_hypotheses.max(1)]
+                    } else {
+                        vec![]
+                    },
+                }
+            })
+            .collect();
+        self.batches.push(input);
+        Ok(results)
+    }
+}
+
+/// Target tokens that also occur in the source get a log-prob of -0.1, other tokens -2.0.
+impl Score for MockTranslator {
+    fn score_batch(
+        &mut self,
+        source: Vec<Vec<String>>,
+        target: Vec<Vec<String>>,
+        _max_batch_size: Option<usize>,
+        _batch_type: BatchType,
+    ) -> Result<Vec<ScoringResult>, String> {
+        Ok(source
+            .iter()
+            .zip(target)
+            .map(|(source, target)| {
+                let tokens_score = target
+                    .iter()
+                    .map(|token| {
+                        let known = source
+                            .iter()
+                            .any(|s| s.to_lowercase() == token.to_lowercase());
+                        if known {
+                            -0.1
+                        } else {
+                            -2.0
+                        }
+                    })
+                    .chain([-0.1])
+                    .collect();
+                let mut tokens = target;
+                tokens.push("</s>".to_string());
+                ScoringResult {
+                    tokens,
+                    tokens_score,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Spec revision of the transformers written by [`TinyModel`].
+const TRANSFORMER_REVISION: u32 = 7;
+
+/// A valid transformer with deterministic random weights, small enough to be written for every
+/// test. Scalar attributes are left out so CTranslate2 uses its defaults: 8 heads, pre-norm and
+/// ReLU; `d_model` must be a multiple of 8.
+pub struct TinyModel {
+    pub layers: usize,
+    pub d_model: u32,
+    pub ffn: u32,
+    /// Shared vocabulary, starting with `<unk>`, `<s>` and `</s>`.
+    pub vocabulary: Vec<String>,
+}
+
+impl Default for TinyModel {
+    fn default() -> Self {
+        let words = ["▁hello", "▁world", "▁the", "▁a", "▁is", "!", ".", "s", "o"];
+        Self {
+            layers: 2,
+            d_model: 16,
+            ffn: 32,
+            vocabulary: ["<unk>", "<s>", "</s>"]
+                .iter()
+                .chain(words.iter())
+                .map(|v| v.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl TinyModel {
+    /// Writes `model.bin`, `config.json` and `shared_vocabulary.txt` to a directory.
+    pub fn write(&self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let (d, ffn, vocab) = (self.d_model, self.ffn, self.vocabulary.len() as u32);
+        let mut weights = Weights {
+            state: 0x2545_f491_4f6c_dd1d,
+            tensors: vec![],
+        };
+        weights.random("encoder/embeddings_0/weight", vec![vocab, d]);
+        for side in ["encoder", "decoder"] {
+            for layer in 0..self.layers {
+                let scope = format!("{}/layer_{}", side, layer);
+                let attention = format!("{}/self_attention", scope);
+                weights.layer_norm(&attention, d);
+                weights.linear(&format!("{}/linear_0", attention), 3 * d, d);
+                weights.linear(&format!("{}/linear_1", attention), d, d);
+                if side == "decoder" {
+                    let attention = format!("{}/attention", scope);
+                    weights.layer_norm(&attention, d);
+                    weights.linear(&format!("{}/linear_0", attention), d, d);
+                    weights.linear(&format!("{}/linear_1", attention), 2 * d, d);
+                    weights.linear(&format!("{}/linear_2", attention), d, d);
+                }
+                let ffn_scope = format!("{}/ffn", scope);
+                weights.layer_norm(&ffn_scope, d);
+                weights.linear(&format!("{}/linear_0", ffn_scope), ffn, d);
+                weights.linear(&format!("{}/linear_1", ffn_scope), d, ffn);
+            }
+            weights.layer_norm(side, d);
+        }
+        weights.linear("decoder/projection", vocab, d);
+        let aliases = [(
+            "decoder/embeddings/weight".to_string(),
+            "encoder/embeddings_0/weight".to_string(),
+        )];
+
+        let file = File::create(dir.join("model.bin")).map_err(|e| e.to_string())?;
+        model_bin::write(
+            &mut BufWriter::new(file),
+            model_bin::MAX_BINARY_VERSION,
+            "TransformerSpec",
+            TRANSFORMER_REVISION,
+            &weights.tensors,
+            &aliases,
+        )?;
+        fs::write(
+            dir.join("config.json"),
+            r#"{"add_source_bos": false, "add_source_eos": false, "bos_token": "<s>", "decoder_start_token": "<s>", "eos_token": "</s>", "unk_token": "<unk>"}"#,
+        )
+        .map_err(|e| e.to_string())?;
+        let mut vocabulary = self.vocabulary.join("\n");
+        vocabulary.push('\n');
+        fs::write(dir.join("shared_vocabulary.txt"), vocabulary).map_err(|e| e.to_string())
+    }
+}
+
+struct Weights {
+    state: u64,
+    tensors: Vec<Tensor>,
+}
+
+impl Weights {
+    /// Uniform in [-0.1, 0.1), from a linear congruential generator.
+    fn random(&mut self, name: &str, shape: Vec<u32>) {
+        let len = shape.iter().product::<u32>() as usize;
+        let values: Vec<f32> = (0..len)
+            .map(|_| {
+                self.state = self
+                    .state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                ((self.state >> 40) as f32 / (1u64 << 24) as f32 - 0.5) * 0.2
+            })
+            .collect();
+        self.tensors.push(Tensor::from_floats(name, shape, &values));
+    }
+
+    fn constant(&mut self, name: &str, shape: Vec<u32>, value: f32) {
+        let len = shape.iter().product::<u32>() as usize;
+        self.tensors
+            .push(Tensor::from_floats(name, shape, &vec![value; len]));
+    }
+
+    fn layer_norm(&mut self, scope: &str, d: u32) {
+        self.constant(&format!("{}/layer_norm/gamma", scope), vec![d], 1.0);
+        self.constant(&format!("{}/layer_norm/beta", scope), vec![d], 0.0);
+    }
+
+    fn linear(&mut self, scope: &str, rows: u32, columns: u32) {
+        self.random(&format!("{}/weight", scope), vec![rows, columns]);
+        self.constant(&format!("{}/bias", scope), vec![rows], 0.0);
+    }
+}
+
+/// Writes the default [`TinyModel`] to a new temporary directory.
+pub fn tiny_model() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "rustyctranslate2-tiny-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    TinyModel::default().write(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,43 @@
+//! Conversion between text and the tokens expected by the model.
+
+use crate::alignment::WORD_MARKER;
+
+/// Turns text into model tokens and back, e.g. a SentencePiece model.
+pub trait Tokenizer {
+    fn encode(&self, text: &str) -> Vec<String>;
+    fn decode(&self, tokens: &[String]) -> String;
+}
+
+/// Splits on whitespace and marks every word with `▁`, for models with a word level vocabulary.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn encode(&self, text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| format!("{}{}", WORD_MARKER, word))
+            .collect()
+    }
+
+    fn decode(&self, tokens: &[String]) -> String {
+        tokens
+            .concat()
+            .replace(WORD_MARKER, " ")
+            .trim_start()
+            .to_string()
+    }
+}
+
+impl<F, G> Tokenizer for (F, G)
+where
+    F: Fn(&str) -> Vec<String>,
+    G: Fn(&[String]) -> String,
+{
+    fn encode(&self, text: &str) -> Vec<String> {
+        (self.0)(text)
+    }
+
+    fn decode(&self, tokens: &[String]) -> String {
+        (self.1)(tokens)
+    }
+}
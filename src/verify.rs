@@ -0,0 +1,301 @@
+//! Checks of a model directory before it is handed to CTranslate2, which crashes or throws
+//! unreadable exceptions on corrupted or half-copied models.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::model_bin::{ModelBin, Variable};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub path: PathBuf,
+    pub binary_version: Option<u32>,
+    /// Model specification, e.g. `TransformerSpec`, and its revision.
+    pub spec: Option<(String, u32)>,
+    pub variables: usize,
+    /// Vocabulary files and their number of tokens.
+    pub vocabularies: Vec<(String, usize)>,
+    /// Files whose SHA-256 matches the manifest.
+    pub checksums: Vec<String>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "model: {}", self.path.display())?;
+        if let Some(version) = self.binary_version {
+            writeln!(f, "binary version: {}", version)?;
+        }
+        if let Some((name, revision)) = &self.spec {
+            writeln!(f, "spec: {} (revision {})", name, revision)?;
+        }
+        writeln!(f, "variables: {}", self.variables)?;
+        for (name, size) in &self.vocabularies {
+            writeln!(f, "vocabulary {}: {} tokens", name, size)?;
+        }
+        for name in &self.checksums {
+            writeln!(f, "checksum ok: {}", name)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "error: {}", error)?;
+        }
+        write!(f, "{}", if self.is_ok() { "ok" } else { "failed" })
+    }
+}
+
+/// Checks that a model directory can be loaded: required files, the `model.bin` header and
+/// variable sizes, and the vocabulary sizes against the embeddings.
+///
+/// With a `manifest` in `sha256sum` format (`<hex>  <file>` lines, relative to the model
+/// directory), the listed files are also compared to their SHA-256.
+pub fn verify_model_dir(path: impl AsRef<Path>, manifest: Option<&Path>) -> VerifyReport {
+    let path = path.as_ref();
+    let mut report = VerifyReport {
+        path: path.to_path_buf(),
+        ..Default::default()
+    };
+    if !path.is_dir() {
+        report.errors.push("not a directory".to_string());
+        return report;
+    }
+
+    let model = path.join("model.bin");
+    let model = if model.is_file() {
+        match ModelBin::open(&model) {
+            Ok(model) => Some(model),
+            Err(e) => {
+                report.errors.push(format!("model.bin: {}", e));
+                None
+            }
+        }
+    } else {
+        report.errors.push("model.bin is missing".to_string());
+        None
+    };
+    if let Some(model) = &model {
+        report.binary_version = Some(model.binary_version);
+        if model.binary_version >= 2 {
+            report.spec = Some((model.spec.clone(), model.spec_revision));
+        }
+        report.variables = model.variables.len();
+        if model.trailing_bytes > 0 {
+            report
+                .warnings
+                .push("model.bin has trailing bytes".to_string());
+        }
+    }
+    if !path.join("config.json").is_file() {
+        report
+            .warnings
+            .push("config.json is missing, default settings are used".to_string());
+    }
+    check_vocabularies(path, model.as_ref(), &mut report);
+    if let Some(manifest) = manifest {
+        check_manifest(path, manifest, &mut report);
+    }
+    report
+}
+
+fn check_vocabularies(path: &Path, model: Option<&ModelBin>, report: &mut VerifyReport) {
+    let vocabulary = |name: &str| {
+        ["json", "txt"]
+            .iter()
+            .map(|ext| format!("{}.{}", name, ext))
+            .find(|file| path.join(file).is_file())
+    };
+    let rows = |variable: Option<&Variable>| variable.and_then(|v| v.shape.first().copied());
+    let source_rows = rows(model.and_then(|v| v.source_embeddings()));
+    let target_rows = rows(model.and_then(|v| v.target_embeddings()));
+
+    let pairs = match (vocabulary("shared_vocabulary"), vocabulary("vocabulary")) {
+        (Some(shared), _) => vec![(shared, vec![source_rows, target_rows])],
+        (None, Some(vocabulary)) => vec![(vocabulary, vec![target_rows])],
+        (None, None) => {
+            let (source, target) = (
+                vocabulary("source_vocabulary"),
+                vocabulary("target_vocabulary"),
+            );
+            if source.is_none() || target.is_none() {
+                report.errors.push(
+                    "no shared_vocabulary, or source_vocabulary and target_vocabulary".to_string(),
+                );
+            }
+            source
+                .map(|v| (v, vec![source_rows]))
+                .into_iter()
+                .chain(target.map(|v| (v, vec![target_rows])))
+                .collect()
+        }
+    };
+    for (file, rows) in pairs {
+        let size = match fs::read_to_string(path.join(&file)) {
+            Ok(text) if file.ends_with(".json") => json_string_count(&text),
+            Ok(text) => text.lines().count(),
+            Err(e) => {
+                report.errors.push(format!("{}: {}", file, e));
+                continue;
+            }
+        };
+        for rows in rows.into_iter().flatten() {
+            if rows as usize != size {
+                report.errors.push(format!(
+                    "{} has {} tokens but the embeddings have {} rows",
+                    file, size, rows
+                ));
+            }
+        }
+        report.vocabularies.push((file, size));
+    }
+}
+
+fn check_manifest(path: &Path, manifest: &Path, report: &mut VerifyReport) {
+    let text = match fs::read_to_string(manifest) {
+        Ok(text) => text,
+        Err(e) => {
+            report
+                .errors
+                .push(format!("manifest {}: {}", manifest.display(), e));
+            return;
+        }
+    };
+    for line in text.lines().filter(|v| !v.trim().is_empty()) {
+        let Some((expected, file)) = line.split_once(char::is_whitespace) else {
+            report.errors.push(format!("bad manifest line: {}", line));
+            continue;
+        };
+        // `sha256sum` marks binary mode with a `*`.
+        let file = file.trim_start().trim_start_matches('*');
+        match sha256(&path.join(file)) {
+            Ok(actual) if actual.eq_ignore_ascii_case(expected) => {
+                report.checksums.push(file.to_string())
+            }
+            Ok(_) => report.errors.push(format!("{}: SHA-256 mismatch", file)),
+            Err(e) => report.errors.push(format!("{}: {}", file, e)),
+        }
+    }
+}
+
+fn sha256(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|v| format!("{:02x}", v))
+        .collect())
+}
+
+/// Number of strings in a JSON array of strings.
+fn json_string_count(text: &str) -> usize {
+    let (mut count, mut in_string, mut escaped) = (0, false, false);
+    for c in text.chars() {
+        match (in_string, escaped, c) {
+            (true, true, _) => escaped = false,
+            (true, false, '\\') => escaped = true,
+            (true, false, '"') => in_string = false,
+            (false, _, '"') => {
+                in_string = true;
+                count += 1;
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_bin::{self, Tensor};
+
+    /// Version 6 `model.bin` with zeroed float32 variables and one alias.
+    fn model_bin(variables: &[(&str, &[u32])]) -> Vec<u8> {
+        let tensors: Vec<Tensor> = variables
+            .iter()
+            .map(|(name, shape)| {
+                let values = vec![0.0; shape.iter().product::<u32>() as usize];
+                Tensor::from_floats(name, shape.to_vec(), &values)
+            })
+            .collect();
+        let aliases = [(
+            "decoder/embeddings/weight".to_string(),
+            "encoder/embeddings_0/weight".to_string(),
+        )];
+        let mut out = vec![];
+        model_bin::write(&mut out, 6, "TransformerSpec", 1, &tensors, &aliases).unwrap();
+        out
+    }
+
+    #[test]
+    fn verifies_model_dir() {
+        let dir =
+            std::env::temp_dir().join(format!("rustyctranslate2-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let model = model_bin(&[
+            ("encoder/embeddings_0/weight", &[3, 2]),
+            ("decoder/projection/weight", &[3, 2]),
+        ]);
+        fs::write(dir.join("model.bin"), &model).unwrap();
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        fs::write(
+            dir.join("shared_vocabulary.json"),
+            r#"["<unk>", "<s>", "\"</s>\""]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("SHA256SUMS"),
+            format!(
+                "{}  config.json\n",
+                "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+            ),
+        )
+        .unwrap();
+
+        let report = verify_model_dir(&dir, Some(&dir.join("SHA256SUMS")));
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.spec, Some(("TransformerSpec".to_string(), 1)));
+        assert_eq!(report.variables, 2);
+        assert_eq!(
+            report.vocabularies,
+            vec![("shared_vocabulary.json".to_string(), 3)]
+        );
+        assert_eq!(report.checksums, vec!["config.json"]);
+
+        fs::write(dir.join("shared_vocabulary.json"), r#"["<unk>"]"#).unwrap();
+        fs::write(dir.join("model.bin"), &model[..model.len() - 40]).unwrap();
+        let report = verify_model_dir(&dir, None);
+        assert_eq!(report.errors, vec!["model.bin: truncated file"]);
+        fs::write(dir.join("model.bin"), &model).unwrap();
+        let report = verify_model_dir(&dir, None);
+        assert_eq!(report.errors.len(), 2, "{}", report);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verifies_tiny_model() {
+        let dir = crate::testing::tiny_model();
+        let report = verify_model_dir(&dir, None);
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.warnings.is_empty(), "{}", report);
+        assert_eq!(
+            report.vocabularies,
+            vec![("shared_vocabulary.txt".to_string(), 12)]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
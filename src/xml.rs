@@ -0,0 +1,192 @@
+//! Minimal XML helpers for the exchange formats (TBX, TMX, ...).
+//!
+//! This is not a general XML parser: elements are found by name, nested elements with the same name
+//! are not supported and comments, CDATA and DTDs are not interpreted.
+
+/// An element found in a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Element<'a> {
+    /// The start tag, e.g. `<tu tuid="1">`.
+    pub start_tag: &'a str,
+    /// Raw content between the start and end tag.
+    pub inner: &'a str,
+    /// Byte range of the whole element in the searched text.
+    pub range: std::ops::Range<usize>,
+}
+
+impl<'a> Element<'a> {
+    /// Unescaped value of an attribute of the start tag.
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        let mut rest = self.start_tag;
+        while let Some(at) = rest.find(name) {
+            let before = rest[..at].chars().last();
+            let after = rest[at + name.len()..].trim_start();
+            rest = &rest[at + name.len()..];
+            if !before.is_some_and(char::is_whitespace) {
+                continue;
+            }
+            let Some(value) = after.strip_prefix('=') else {
+                continue;
+            };
+            let value = value.trim_start();
+            let quote = value.chars().next()?;
+            if quote != '"' && quote != '\'' {
+                continue;
+            }
+            let end = value[1..].find(quote)?;
+            return Some(unescape(&value[1..end + 1]));
+        }
+        None
+    }
+
+    /// Child elements with the given name.
+    pub fn children(&self, name: &str) -> Vec<Element<'a>> {
+        elements(self.inner, name)
+    }
+
+    /// First child element with the given name.
+    pub fn child(&self, name: &str) -> Option<Element<'a>> {
+        self.children(name).into_iter().next()
+    }
+
+    /// Unescaped text content, with the tags of child elements removed.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        let mut rest = self.inner;
+        while let Some(start) = rest.find('<') {
+            out.push_str(&rest[..start]);
+            match rest[start..].find('>') {
+                Some(end) => rest = &rest[start + end + 1..],
+                None => {
+                    rest = "";
+                }
+            }
+        }
+        out.push_str(rest);
+        unescape(&out)
+    }
+}
+
+/// All elements with the given name, in document order.
+pub(crate) fn elements<'a>(text: &'a str, name: &str) -> Vec<Element<'a>> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut res = vec![];
+    let mut offset = 0;
+    while let Some(at) = text[offset..].find(&open) {
+        let start = offset + at;
+        let after = &text[start + open.len()..];
+        if !after.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            offset = start + open.len();
+            continue;
+        }
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        let tag_end = start + open.len() + tag_end + 1;
+        let start_tag = &text[start..tag_end];
+        if start_tag.ends_with("/>") {
+            res.push(Element {
+                start_tag,
+                inner: "",
+                range: start..tag_end,
+            });
+            offset = tag_end;
+            continue;
+        }
+        let Some(inner_end) = text[tag_end..].find(&close) else {
+            break;
+        };
+        let end = tag_end + inner_end + close.len();
+        res.push(Element {
+            start_tag,
+            inner: &text[tag_end..tag_end + inner_end],
+            range: start..end,
+        });
+        offset = end;
+    }
+    res
+}
+
+/// Escapes text for use in content and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Resolves the predefined and numeric character references.
+pub(crate) fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|v| u32::from_str_radix(v, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|v| v.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Compares language tags by primary subtag, so `en` matches `en-US`.
+pub(crate) fn same_language(a: &str, b: &str) -> bool {
+    let primary = |v: &str| {
+        v.split(['-', '_'])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase()
+    };
+    primary(a) == primary(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_elements() {
+        let doc =
+            r#"<body><tu tuid="1"><seg>a &amp; <b>b</b></seg></tu><tuv/><tu tuid='2'/></body>"#;
+        let tus = elements(doc, "tu");
+        assert_eq!(tus.len(), 2);
+        assert_eq!(tus[0].attribute("tuid").as_deref(), Some("1"));
+        assert_eq!(tus[1].attribute("tuid").as_deref(), Some("2"));
+        assert_eq!(tus[0].child("seg").unwrap().text(), "a & b");
+        assert_eq!(unescape(&escape("<\"x\" & 'y'>")), "<\"x\" & 'y'>");
+        assert_eq!(unescape("&#65;&#x42;&unknown;"), "AB&unknown;");
+    }
+}